@@ -21,7 +21,7 @@ pub fn prove32(c: Circuit<u32>, w: Vec<u32>, mut chan: ProverTcpChannel, mut ctx
             + c.n_decode32 * 32
             + c.n_check_all_eq_pairs,
         n_mul_check: if check_mul { 1 } else { 0 },
-        n_openings: 0,
+        n_openings: c.openings.len(),
     };
 
     ctx.start_time("preprocess vole");
@@ -35,12 +35,12 @@ pub fn prove32(c: Circuit<u32>, w: Vec<u32>, mut chan: ProverTcpChannel, mut ctx
     }
     ctx.stop_time();
 
-    let wires = Wires {
+    let mut wires = Wires {
         clear: w.into_iter().map(|w| w as u128).collect::<Vec<_>>(),
         macs: voles.mc_in,
     };
     ctx.start_time("evaluating circuit");
-    let mult_checks = eval(&c, wires, voles.xs_mul, voles.mc_mul, &mut chan);
+    let mult_checks = eval(&c, &mut wires, voles.xs_mul, voles.mc_mul, &mut chan);
     ctx.stop_time();
 
     if check_mul {
@@ -59,6 +59,16 @@ pub fn prove32(c: Circuit<u32>, w: Vec<u32>, mut chan: ProverTcpChannel, mut ctx
         chan.send_v(v);
     }
 
+    // Open the public outputs. Only the lower 32 bits of a wire are
+    // its value, so the upper bits are masked by adding 2^32 times
+    // the random value of a correlation.
+    for (k, id) in c.openings.iter().enumerate() {
+        let x = wires.clear[id - ARG0].wrapping_add(voles.xs_openings[k] << 32);
+        let m = wires.macs[id - ARG0].wrapping_add(voles.mc_openings[k] << 32);
+        chan.send_val(x);
+        chan.send_mac(m);
+    }
+
     println!("Done, exiting.");
 }
 
@@ -139,7 +149,7 @@ struct Wires {
 /// t is its tag, as well as a0 and a1 for multiplication checks
 fn eval(
     c: &Circuit<u32>,
-    mut wires: Wires,
+    wires: &mut Wires,
     xs_mul: Vec<u128>,
     mc_mul: Vec<u128>,
     chan: &mut ProverTcpChannel,
@@ -4,7 +4,9 @@ use utils::circuit::*;
 use crate::quicksilver::vole;
 use crate::ProofCtx;
 
-pub fn verify32(c: Circuit<u32>, mut chan: VerifierTcpChannel, mut ctx: ProofCtx) {
+/// Verifies the proof of the prover, and returns the values of the
+/// wires opened by the circuit
+pub fn verify32(c: Circuit<u32>, mut chan: VerifierTcpChannel, mut ctx: ProofCtx) -> Vec<u32> {
     let check_mul = (c.n_mul > 0)
         || (c.n_select_alt > 0)
         || (c.n_select_const_alt > 0)
@@ -20,7 +22,7 @@ pub fn verify32(c: Circuit<u32>, mut chan: VerifierTcpChannel, mut ctx: ProofCtx
             + c.n_decode32 * 32
             + c.n_check_all_eq_pairs,
         n_mul_check: if check_mul { 1 } else { 0 },
-        n_openings: 0
+        n_openings: c.openings.len()
     };
 
     ctx.start_time("preprocess vole");
@@ -50,13 +52,13 @@ pub fn verify32(c: Circuit<u32>, mut chan: VerifierTcpChannel, mut ctx: ProofCtx
         chan.send_challenge(x);
     }
 
-    let wires = Wires {
+    let mut wires = Wires {
         zm: vole.ks_in,
         // z2: vec![],
     };
 
     ctx.start_time("evaluating circuit");
-    let w = eval(&c, wires, delta, x, vole.ks_mul, &mut chan);
+    let w = eval(&c, &mut wires, delta, x, vole.ks_mul, &mut chan);
     ctx.stop_time();
 
     if check_mul {
@@ -67,7 +69,19 @@ pub fn verify32(c: Circuit<u32>, mut chan: VerifierTcpChannel, mut ctx: ProofCtx
         assert_eq!(w.wrapping_add(vole.ks_mul_check[0]), uv);
     }
 
+    // Check the openings of the public outputs, whose upper bits are
+    // masked by the prover (see prove32)
+    let mut outputs = vec![];
+    for (k, id) in c.openings.iter().enumerate() {
+        let x = chan.recv_val();
+        let m = chan.recv_mac();
+        let key = wires.zm[id - ARG0].wrapping_add(vole.ks_openings[k] << 32);
+        assert_eq!(m, key.wrapping_add(delta.wrapping_mul(x)));
+        outputs.push(x as u32);
+    }
+
     println!("Verifier accepts, exiting.");
+    outputs
 }
 
 #[allow(dead_code)]
@@ -81,7 +95,7 @@ type Key = u128;
 
 fn eval(
     c: &Circuit<u32>,
-    mut wires: Wires,
+    wires: &mut Wires,
     delta: u128,
     challenge: u128,
     mul_keys: Vec<u128>,
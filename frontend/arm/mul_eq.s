@@ -0,0 +1,50 @@
+@ Computes x * y - z by repeated addition, where x, y and z are the
+@ arguments of the program, in the style of arm-none-eabi-gcc -O1
+@ -mthumb -mcpu=cortex-m3 -S for:
+@
+@   unsigned mul(unsigned x, unsigned y) {
+@       unsigned r = 0;
+@       while (x--)
+@           r += y;
+@       return r;
+@   }
+@
+@   int main(unsigned *args) {
+@       return mul(args[0], args[1]) - args[2];
+@   }
+
+	.syntax unified
+	.cpu cortex-m3
+	.thumb
+	.text
+	.align	1
+	.global	mul
+	.thumb_func
+	.type	mul, %function
+mul:
+	mov	r3, r0
+	movs	r0, #0
+	cbz	r3, .L2
+.L3:
+	add	r0, r0, r1
+	subs	r3, r3, #1
+	bne	.L3
+.L2:
+	bx	lr
+	.size	mul, .-mul
+	.section	.text.startup,"ax",%progbits
+	.align	1
+	.global	main
+	.thumb_func
+	.type	main, %function
+main:
+	push	{r4, lr}
+	mov	r4, r0
+	ldr	r1, [r0, #4]
+	ldr	r0, [r0]
+	bl	mul
+	ldr	r3, [r4, #8]
+	subs	r0, r0, r3
+	pop	{r4, pc}
+	.size	main, .-main
+	.ident	"GCC: (GNU Arm Embedded Toolchain) 10.3.1"
@@ -0,0 +1,1491 @@
+use std::collections::HashMap;
+
+use crate::miniram::asm::{number, Asm, Res};
+use crate::miniram::lang::*;
+
+/// Byte address of the data sections (.data, .rodata, .bss, ...). The
+/// words below are left to the arguments of the program.
+pub const ADR_DATA: Word = 0x1000;
+
+/// Initial value of sp, i.e the stack grows down from this byte
+/// address
+pub const STACK_TOP: Word = 0x10_0000;
+
+// MiniRAM registers of sp and of the scratch registers of the
+// translation. ARM registers r0, ..., r12 are r1, ..., r13, and lr is
+// LR.
+const SP: Reg = 14;
+const T0: Reg = 16;
+const T1: Reg = 17;
+const T2: Reg = 18;
+
+/// Translates ARM assembly src (usually a .s file, e.g the output of
+/// arm-none-eabi-gcc -S) into a MiniRAM program.
+///
+/// The program sets sp to STACK_TOP, calls main and returns the value
+/// main returns in r0. The arguments of the program are the words at
+/// byte addresses 0, 4, 8, ..., i.e main is called with r0 pointing
+/// to them.
+///
+/// ARM registers r0, ..., r12, sp and lr are MiniRAM registers r1,
+/// ..., r15 (where r15 is LR), and r16, r17 and r18 are scratch
+/// registers. Memory is byte addressed as in ARM: LDR and STR access
+/// the word at byte address a / 4, i.e an unaligned address is
+/// truncated to the word containing it, so constant offsets of LDR and
+/// STR must be multiples of 4. The data sections are placed from
+/// ADR_DATA, and code labels, e.g functions, have the index of a
+/// MiniRAM instruction as address.
+///
+/// The instructions supported, in unified syntax for ARM and Thumb-2,
+/// are:
+///
+///   MOV/ MVN{S} rd, op2
+///   ADD/ SUB/ RSB/ AND/ ORR/ EOR/ BIC{S} rd, {rn,} op2
+///   LSL/ LSR/ ASR/ ROR{S} rd, {rm,} #imm|rs     (no ASR by register)
+///   NEG{S} rd, rm
+///   MUL{S}/ UDIV rd, {rn,} rm
+///   MLA/ MLS rd, rn, rm, ra
+///   UMULL rdlo, rdhi, rn, rm
+///   ADDW/ SUBW rd, rn, #imm
+///   MOVW/ MOVT rd, #imm16|#:lower16:sym|#:upper16:sym
+///   CMP/ CMN/ TST/ TEQ rn, op2
+///   UXTB/ UXTH/ SXTB/ SXTH rd, rm
+///   LDR/ LDRB/ LDRH/ STR/ STRB/ STRH rt, [rn{, off}]{!}|[rn], off
+///   LDR rt, =sym|imm
+///   LDR rt, label                              label of a .word
+///   ADR rd, label
+///   PUSH/ POP {reglist}                        only POP can have pc
+///   B/ BL/ BLX label
+///   BX/ BLX rm
+///   MOV pc, rm
+///   CBZ/ CBNZ rn, label
+///   IT{T|E}*, NOP
+///
+/// where op2 is #imm or rm{, LSL|LSR|ASR|ROR #imm|rs}, and off is
+/// #imm, rm or rm, LSL #imm. As in ARM, shifts by a register rs shift
+/// by the bottom byte of rs, i.e LSL and LSR by 32 to 255 give 0.
+/// Instructions can have a condition, which for instructions other
+/// than branches are translated as a block skipped by a branch, where
+/// consecutive instructions with a condition and its inverse (e.g of
+/// an ITE) share a block.
+///
+/// As every MiniRAM instruction but stores sets the condition flags,
+/// a condition must refer to the flags of the last instruction before
+/// it, which is a comparison or an instruction with an S suffix.
+/// Comparisons are repeated for the conditions of consecutive
+/// branches, and conditions on C or V can only follow arithmetic
+/// instructions.
+///
+/// Directives for sections (.text, .data, .bss and .section) and data
+/// (.word, .short, .byte, .space, .ascii, .asciz and alignment) are
+/// supported, and directives for symbols and debug information (e.g
+/// .global, .type or .cfi_startproc) are ignored.
+///
+/// Errors give the line of the first unsupported statement, where line
+/// 0 is the code calling main.
+pub fn translate(src: &str) -> Res<Asm> {
+    use Inst::*;
+    let mut t = Translator {
+        text: true,
+        ..Default::default()
+    };
+    let main = Ref::Val(Expr::sym("main"), Part::All);
+    t.emit(Mov(SP, Val::Const(STACK_TOP)));
+    t.emit_ref(Bl(Val::Const(0)), main);
+    t.emit(Ret(Val::Reg(1)));
+    for (line, s) in src.lines().enumerate() {
+        t.line = line + 1;
+        t.statement(s).map_err(|e| (line + 1, e))?;
+    }
+    t.finish()
+}
+
+#[derive(Default)]
+struct Translator<'a> {
+    code: Vec<Inst>,
+    /// Source line of each instruction
+    lines: Vec<usize>,
+    line: usize,
+    /// Whether the current section is code
+    text: bool,
+    syms: HashMap<&'a str, Word>,
+    labels: Vec<(String, Word)>,
+    /// Instructions with a constant referring to a symbol, and their
+    /// line
+    refs: Vec<(usize, Ref<'a>, usize)>,
+    /// Bytes of the data sections, and words referring to a symbol
+    data: Vec<u8>,
+    data_refs: Vec<(usize, Expr<'a>, usize)>,
+    /// Words in the code, i.e literal pools, and the index of the word
+    /// following a label
+    pool: Vec<Expr<'a>>,
+    pool_labels: HashMap<&'a str, usize>,
+    /// Labels in the code since the last statement
+    pending: Vec<&'a str>,
+    flags: Flags,
+    block: Option<Block>,
+}
+
+/// The condition flags at the current instruction
+#[derive(Default)]
+struct Flags {
+    /// Whether the flags were set by the last instruction
+    valid: bool,
+    /// Whether C and V are set as in ARM, i.e by an arithmetic
+    /// instruction
+    cv: bool,
+    /// Instructions of a comparison setting the flags again
+    redo: Vec<Inst>,
+}
+
+/// Effect of an instruction on the condition flags
+enum Fl {
+    /// Sets the flags, which redo can set again if it is a comparison
+    Set {
+        cv: bool,
+        redo: bool,
+    },
+    /// Writes no registers, so a comparison before can be repeated
+    Keep,
+    Clobber,
+}
+
+/// Conditional instructions skipped by a branch at skip, unless the
+/// condition cc holds. The instructions of the inverse condition
+/// follow a branch at end, which skips them.
+#[derive(Clone, Copy)]
+struct Block {
+    cc: CondCode,
+    skip: usize,
+    end: Option<usize>,
+}
+
+/// A symbol plus an offset, or a number
+struct Expr<'a> {
+    sym: Option<&'a str>,
+    off: i128,
+}
+
+impl<'a> Expr<'a> {
+    fn sym(sym: &'a str) -> Self {
+        Expr {
+            sym: Some(sym),
+            off: 0,
+        }
+    }
+}
+
+/// Value of a constant referring to a symbol
+enum Ref<'a> {
+    Val(Expr<'a>, Part),
+    /// Word of a literal pool at the label plus the offset
+    Pool(&'a str, i128),
+}
+
+#[derive(Clone, Copy)]
+enum Part {
+    All,
+    Lo16,
+    Hi16,
+}
+
+const S_OPS: [&str; 15] = [
+    "mov", "mvn", "add", "sub", "rsb", "neg", "and", "orr", "eor", "bic", "lsl", "lsr", "asr",
+    "ror", "mul",
+];
+
+const OPS: [&str; 32] = [
+    "addw", "subw", "movw", "movt", "cmp", "cmn", "tst", "teq", "mla", "mls", "umull", "udiv",
+    "uxtb", "uxth", "sxtb", "sxth", "ldr", "ldrb", "ldrh", "str", "strb", "strh", "adr", "push",
+    "pop", "b", "bl", "bx", "blx", "cbz", "cbnz", "nop",
+];
+
+const IGNORED: [&str; 25] = [
+    ".syntax",
+    ".cpu",
+    ".arch",
+    ".arch_extension",
+    ".fpu",
+    ".eabi_attribute",
+    ".file",
+    ".global",
+    ".globl",
+    ".type",
+    ".size",
+    ".ident",
+    ".thumb",
+    ".thumb_func",
+    ".arm",
+    ".code",
+    ".fnstart",
+    ".fnend",
+    ".cantunwind",
+    ".save",
+    ".setfp",
+    ".pad",
+    ".weak",
+    ".ltorg",
+    ".loc",
+];
+
+impl<'a> Translator<'a> {
+    fn pc(&self) -> Word {
+        Word::try_from(self.code.len()).unwrap()
+    }
+
+    fn emit(&mut self, i: Inst) {
+        self.code.push(i);
+        self.lines.push(self.line);
+    }
+
+    fn emit_ref(&mut self, i: Inst, r: Ref<'a>) {
+        self.refs.push((self.code.len(), r, self.line));
+        self.emit(i);
+    }
+
+    fn statement(&mut self, s: &'a str) -> Result<(), &'static str> {
+        let mut s = strip_comment(s).trim();
+        // Line markers of the preprocessor
+        if s.starts_with('#') {
+            return Ok(());
+        }
+        while let Some((label, rest)) = s.split_once(':') {
+            let label = label.trim();
+            if !is_sym(label) {
+                break;
+            }
+            self.label(label)?;
+            s = rest.trim();
+        }
+        if s.is_empty() {
+            return Ok(());
+        }
+        let (op, ops) = match s.split_once(char::is_whitespace) {
+            Some((op, ops)) => (op, operands(ops)),
+            None => (s, vec![]),
+        };
+        if op.starts_with('.') {
+            self.directive(op, &ops)
+        } else if self.text {
+            self.inst(op, &ops)
+        } else {
+            Err("instruction in a data section")
+        }
+    }
+
+    fn label(&mut self, name: &'a str) -> Result<(), &'static str> {
+        let adr = if self.text {
+            // Other instructions may branch here, with other flags
+            self.close_block();
+            self.flags = Flags::default();
+            self.pending.push(name);
+            self.pc()
+        } else {
+            ADR_DATA + Word::try_from(self.data.len()).unwrap()
+        };
+        if self.syms.insert(name, adr).is_some() {
+            return Err("symbol defined twice");
+        }
+        self.labels.push((name.to_string(), adr));
+        Ok(())
+    }
+
+    fn section(&mut self, text: bool) {
+        self.close_block();
+        self.flags = Flags::default();
+        self.pending.clear();
+        self.text = text;
+    }
+
+    fn directive(&mut self, op: &str, ops: &[&'a str]) -> Result<(), &'static str> {
+        match op {
+            ".text" => self.section(true),
+            ".data" | ".bss" => self.section(false),
+            ".section" => {
+                let name = ops.first().ok_or("expected a section name")?;
+                self.section(name.starts_with(".text"))
+            }
+            ".word" | ".long" | ".4byte" => {
+                for &x in ops {
+                    let e = expr(x)?;
+                    if self.text {
+                        let k = self.pool.len();
+                        for l in self.pending.drain(..) {
+                            self.pool_labels.insert(l, k);
+                        }
+                        self.pool.push(e);
+                    } else {
+                        self.data_refs.push((self.data.len(), e, self.line));
+                        self.data.extend([0; 4]);
+                    }
+                }
+            }
+            // Instructions are not aligned
+            ".align" | ".p2align" | ".balign" if self.text => {}
+            ".align" | ".p2align" | ".balign" => {
+                let n = int(ops.first().ok_or("expected an alignment")?, 8)?;
+                let n = match op {
+                    ".balign" => n,
+                    _ => 1u32.checked_shl(n).ok_or("number out of range")?,
+                };
+                let len = self.data.len().next_multiple_of(n.max(1) as usize);
+                self.data.resize(len, 0);
+            }
+            ".short" | ".hword" | ".2byte" | ".byte" | ".space" | ".zero" | ".skip" | ".ascii"
+            | ".asciz" | ".string"
+                if self.text =>
+            {
+                return Err("data in a code section")
+            }
+            ".short" | ".hword" | ".2byte" => {
+                for x in ops {
+                    self.data.extend((int(x, 16)? as u16).to_le_bytes());
+                }
+            }
+            ".byte" => {
+                for x in ops {
+                    self.data.push(int(x, 8)? as u8);
+                }
+            }
+            ".space" | ".zero" | ".skip" => {
+                let n = int(ops.first().ok_or("expected a size")?, 32)?;
+                let fill = ops.get(1).map(|x| int(x, 8)).transpose()?.unwrap_or(0);
+                let len = self.data.len() + n as usize;
+                self.data.resize(len, fill as u8);
+            }
+            ".ascii" | ".asciz" | ".string" => {
+                for x in ops {
+                    self.data.extend(string(x)?);
+                    if op != ".ascii" {
+                        self.data.push(0);
+                    }
+                }
+            }
+            _ if IGNORED.contains(&op) || op.starts_with(".cfi_") => {}
+            _ => return Err("unsupported directive"),
+        }
+        Ok(())
+    }
+
+    fn inst(&mut self, op: &str, ops: &[&'a str]) -> Result<(), &'static str> {
+        use Inst::*;
+        let op = op.to_lowercase();
+        let op = op
+            .strip_suffix(".w")
+            .or(op.strip_suffix(".n"))
+            .unwrap_or(&op);
+        // The conditions of IT blocks are repeated by the instructions
+        if op
+            .strip_prefix("it")
+            .is_some_and(|m| m.len() <= 3 && m.chars().all(|c| c == 't' || c == 'e'))
+        {
+            return Ok(());
+        }
+        self.pending.clear();
+        let (base, s, cc) = mnemonic(op).ok_or("unsupported instruction")?;
+        let start = self.code.len();
+        let Some(cc) = cc else {
+            self.close_block();
+            let fl = self.body(base, s, ops)?;
+            self.update_flags(fl, start);
+            return Ok(());
+        };
+        if !self.continue_block(cc) {
+            self.close_block();
+            self.use_flags(cc)?;
+            if matches!(base, "b" | "bx") {
+                let i = self.code.len();
+                self.body(base, s, ops)?;
+                if let B(c, _) = &mut self.code[i] {
+                    *c = Some(cc);
+                }
+                self.flags.valid = false;
+                return Ok(());
+            }
+            let skip = self.code.len();
+            self.block = Some(Block {
+                cc,
+                skip,
+                end: None,
+            });
+            self.emit(B(Some(inverse(cc)), Val::Const(0)));
+        }
+        // Following conditions refer to the flags set in the block
+        if let Fl::Set { .. } = self.body(base, s, ops)? {
+            self.close_block();
+        }
+        self.flags = Flags::default();
+        Ok(())
+    }
+
+    /// Emits the instructions of base without its condition, and
+    /// returns their effect on the flags
+    fn body(&mut self, base: &str, s: bool, ops: &[&'a str]) -> Result<Fl, &'static str> {
+        use Inst::*;
+        let logic = if s {
+            Fl::Set {
+                cv: false,
+                redo: false,
+            }
+        } else {
+            Fl::Clobber
+        };
+        let arith = if s {
+            Fl::Set {
+                cv: true,
+                redo: false,
+            }
+        } else {
+            Fl::Clobber
+        };
+        let fl = match base {
+            // Returns from a subroutine
+            "mov" if !s && ops.first().is_some_and(|x| is_pc(x)) => {
+                let v = self.op2(&ops[1..])?;
+                self.emit(B(None, v));
+                Fl::Clobber
+            }
+            "mov" | "mvn" => {
+                let (rd, op2) = ops.split_first().ok_or("wrong number of operands")?;
+                let rd = reg(rd)?;
+                let i = match (base, self.op2(op2)?) {
+                    ("mov", v) => Mov(rd, v),
+                    (_, Val::Reg(x)) => Mvn(rd, x),
+                    (_, Val::Const(c)) => Mov(rd, Val::Const(!c)),
+                };
+                self.emit(i);
+                logic
+            }
+            "add" | "sub" | "rsb" | "addw" | "subw" => {
+                let (rd, rn, v) = self.dp(ops)?;
+                let i = match base {
+                    "add" | "addw" => Add(rd, rn, v),
+                    "sub" | "subw" => Sub(rd, rn, v),
+                    _ => Sub(rd, self.in_reg(v), Val::Reg(rn)),
+                };
+                self.emit(i);
+                arith
+            }
+            "neg" => {
+                let [rd, rm] = regs(ops)?;
+                self.emit(Mov(T0, Val::Const(0)));
+                self.emit(Sub(rd, T0, Val::Reg(rm)));
+                arith
+            }
+            "and" | "orr" | "eor" | "bic" => {
+                let (rd, rn, v) = self.dp(ops)?;
+                let i = match (base, v) {
+                    ("and", v) => And(rd, rn, v),
+                    ("eor", v) => Xor(rd, rn, v),
+                    ("orr", v) => Orr(rd, rn, self.in_reg(v)),
+                    (_, Val::Const(c)) => And(rd, rn, Val::Const(!c)),
+                    (_, Val::Reg(x)) => {
+                        self.emit(Mvn(T0, x));
+                        And(rd, rn, Val::Reg(T0))
+                    }
+                };
+                self.emit(i);
+                logic
+            }
+            "lsl" | "lsr" | "asr" | "ror" => {
+                let (rd, rm, amt) = match ops {
+                    [rd, amt] => (rd, rd, amt),
+                    [rd, rm, amt] => (rd, rm, amt),
+                    _ => return Err("wrong number of operands"),
+                };
+                self.shift(base, reg(rd)?, reg(rm)?, amt)?;
+                logic
+            }
+            "mul" | "udiv" => {
+                let (rd, rn, rm) = match ops {
+                    [rd, rm] => (rd, rd, rm),
+                    [rd, rn, rm] => (rd, rn, rm),
+                    _ => return Err("wrong number of operands"),
+                };
+                let (rd, rn, rm) = (reg(rd)?, reg(rn)?, reg(rm)?);
+                self.emit(match base {
+                    "mul" => Mul(rd, rn, rm),
+                    _ => Udiv(rd, rn, rm),
+                });
+                logic
+            }
+            "mla" | "mls" => {
+                let [rd, rn, rm, ra] = regs(ops)?;
+                self.emit(Mul(T0, rn, rm));
+                self.emit(match base {
+                    "mla" => Add(rd, ra, Val::Reg(T0)),
+                    _ => Sub(rd, ra, Val::Reg(T0)),
+                });
+                Fl::Clobber
+            }
+            "umull" => {
+                let [lo, hi, rn, rm] = regs(ops)?;
+                self.emit(Mul(T0, rn, rm));
+                self.emit(Umulh(hi, rn, rm));
+                self.emit(Mov(lo, Val::Reg(T0)));
+                Fl::Clobber
+            }
+            "movw" | "movt" => {
+                let [rd, x] = ops else {
+                    return Err("wrong number of operands");
+                };
+                let rd = reg(rd)?;
+                let x = x.strip_prefix('#').unwrap_or(x);
+                let (prefix, part) = match base {
+                    "movw" => (":lower16:", Part::Lo16),
+                    _ => (":upper16:", Part::Hi16),
+                };
+                let dst = if base == "movw" { rd } else { T0 };
+                match x.strip_prefix(prefix) {
+                    Some(e) => self.emit_ref(Mov(dst, Val::Const(0)), Ref::Val(expr(e)?, part)),
+                    None if x.starts_with(':') => return Err("unsupported relocation"),
+                    None if base == "movw" => self.emit(Mov(rd, Val::Const(int(x, 16)?))),
+                    None => self.emit(Mov(T0, Val::Const(int(x, 16)? << 16))),
+                }
+                if base == "movt" {
+                    self.emit(And(rd, rd, Val::Const(0xFFFF)));
+                    self.emit(Orr(rd, rd, T0));
+                }
+                Fl::Clobber
+            }
+            "cmp" | "cmn" | "tst" | "teq" => {
+                let (rn, op2) = ops.split_first().ok_or("wrong number of operands")?;
+                let rn = reg(rn)?;
+                let v = self.op2(op2)?;
+                let i = match base {
+                    "cmp" => Cmp(rn, v),
+                    "cmn" => Add(T1, rn, v),
+                    "tst" => Tst(rn, self.in_reg(v)),
+                    _ => Xor(T1, rn, v),
+                };
+                self.emit(i);
+                Fl::Set {
+                    cv: matches!(base, "cmp" | "cmn"),
+                    redo: true,
+                }
+            }
+            "uxtb" | "uxth" | "sxtb" | "sxth" => {
+                let [rd, rm] = regs(ops)?;
+                let bits = if base.ends_with('b') { 8 } else { 16 };
+                if base.starts_with('u') {
+                    self.emit(And(rd, rm, Val::Const((1 << bits) - 1)));
+                } else {
+                    self.emit(Shl(T0, 32 - bits, rm));
+                    self.asr(rd, T0, 32 - bits);
+                }
+                Fl::Clobber
+            }
+            "ldr" | "ldrb" | "ldrh" | "str" | "strb" | "strh" => self.mem(base, ops)?,
+            "adr" => {
+                let [rd, x] = ops else {
+                    return Err("wrong number of operands");
+                };
+                self.mov_expr(reg(rd)?, expr(x)?);
+                Fl::Clobber
+            }
+            "push" | "pop" => {
+                self.push_pop(base, ops)?;
+                Fl::Clobber
+            }
+            "b" | "bl" => {
+                let [x] = ops else {
+                    return Err("wrong number of operands");
+                };
+                let i = match base {
+                    "b" => B(None, Val::Const(0)),
+                    _ => Bl(Val::Const(0)),
+                };
+                self.emit_ref(i, Ref::Val(expr(x)?, Part::All));
+                Fl::Clobber
+            }
+            "bx" | "blx" => {
+                let [x] = ops else {
+                    return Err("wrong number of operands");
+                };
+                match (base, reg(x)) {
+                    ("bx", rm) => self.emit(B(None, Val::Reg(rm?))),
+                    (_, Ok(rm)) => self.emit(Bl(Val::Reg(rm))),
+                    (_, Err(_)) => {
+                        self.emit_ref(Bl(Val::Const(0)), Ref::Val(expr(x)?, Part::All));
+                    }
+                }
+                Fl::Clobber
+            }
+            "cbz" | "cbnz" => {
+                let [rn, x] = ops else {
+                    return Err("wrong number of operands");
+                };
+                let cc = match base {
+                    "cbz" => CondCode::Eq,
+                    _ => CondCode::Ne,
+                };
+                self.emit(Cmp(reg(rn)?, Val::Const(0)));
+                self.emit_ref(B(Some(cc), Val::Const(0)), Ref::Val(expr(x)?, Part::All));
+                Fl::Keep
+            }
+            "nop" if ops.is_empty() => Fl::Keep,
+            _ => return Err("wrong number of operands"),
+        };
+        Ok(fl)
+    }
+
+    /// Returns rd, rn and op2 of a data processing instruction, where
+    /// rn is rd if it is left out
+    fn dp(&mut self, ops: &[&str]) -> Result<(Reg, Reg, Val), &'static str> {
+        let n = ops.len() - usize::from(ops.last().is_some_and(|x| is_shift(x)));
+        let (rd, rn, op2) = match n {
+            2 => (ops[0], ops[0], &ops[1..]),
+            3 => (ops[0], ops[1], &ops[2..]),
+            _ => return Err("wrong number of operands"),
+        };
+        Ok((reg(rd)?, reg(rn)?, self.op2(op2)?))
+    }
+
+    /// Returns the value of the flexible second operand ops, i.e an
+    /// immediate or a register, which is shifted into T0
+    fn op2(&mut self, ops: &[&str]) -> Result<Val, &'static str> {
+        match ops {
+            [x] if is_imm(x) => Ok(Val::Const(int(x, 32)?)),
+            [x] => Ok(Val::Reg(reg(x)?)),
+            [x, sh] if is_shift(sh) => {
+                let (kind, amt) = sh.split_once(char::is_whitespace).unwrap();
+                self.shift(&kind.to_lowercase(), T0, reg(x)?, amt.trim())?;
+                Ok(Val::Reg(T0))
+            }
+            [_, _] => Err("unsupported shift"),
+            _ => Err("wrong number of operands"),
+        }
+    }
+
+    /// Moves the value of v to a register, i.e T0 for a constant
+    fn in_reg(&mut self, v: Val) -> Reg {
+        match v {
+            Val::Reg(x) => x,
+            Val::Const(_) => {
+                self.emit(Inst::Mov(T0, v));
+                T0
+            }
+        }
+    }
+
+    /// Moves the value of e to rd
+    fn mov_expr(&mut self, rd: Reg, e: Expr<'a>) {
+        match e.sym {
+            None => self.emit(Inst::Mov(rd, Val::Const(e.off as Word))),
+            Some(_) => self.emit_ref(Inst::Mov(rd, Val::Const(0)), Ref::Val(e, Part::All)),
+        }
+    }
+
+    /// Shifts x by amt, i.e an immediate or a register, into dst
+    fn shift(&mut self, kind: &str, dst: Reg, x: Reg, amt: &str) -> Result<(), &'static str> {
+        use Inst::*;
+        if !is_imm(amt) {
+            let y = reg(amt)?;
+            match kind {
+                "lsl" | "lsr" => self.shift_r(kind, dst, x, y),
+                "ror" => self.emit(RotrR(dst, y, x)),
+                _ => return Err("ASR by a register is not supported"),
+            }
+            return Ok(());
+        }
+        let n = int(amt, 32)?;
+        let i = match (kind, n) {
+            ("lsl", 0) => Mov(dst, Val::Reg(x)),
+            ("lsl", 1..=31) => Shl(dst, n, x),
+            ("lsr", 1..=31) => Shr(dst, n, x),
+            ("lsr", 32) => Mov(dst, Val::Const(0)),
+            ("ror", 1..=31) => Rotr(dst, n, x),
+            ("asr", 1..=32) => {
+                self.asr(dst, x, n.min(31));
+                return Ok(());
+            }
+            _ => return Err("shift amount out of range"),
+        };
+        self.emit(i);
+        Ok(())
+    }
+
+    /// Logical shift of x by the bottom byte of y into dst, which is 0
+    /// for amounts 32 to 255 (ShlR and ShrR shift by y % 32)
+    fn shift_r(&mut self, kind: &str, dst: Reg, x: Reg, y: Reg) {
+        use Inst::*;
+        // T1 = all ones if the bottom byte of y is < 32, else 0
+        self.emit(And(T1, y, Val::Const(0xE0)));
+        self.emit(Sub(T1, T1, Val::Const(1)));
+        self.emit(Shr(T1, 31, T1));
+        self.emit(Sub(T1, T1, Val::Const(1)));
+        self.emit(Mvn(T1, T1));
+        self.emit(match kind {
+            "lsl" => ShlR(dst, y, x),
+            _ => ShrR(dst, y, x),
+        });
+        self.emit(And(dst, dst, Val::Reg(T1)));
+    }
+
+    /// Arithmetic shift of x by 0 < n < 32 into dst, i.e the logical
+    /// shift with the top n bits set to the sign of x
+    fn asr(&mut self, dst: Reg, x: Reg, n: Word) {
+        use Inst::*;
+        // T1 = all ones if x is negative, else 0
+        self.emit(Shr(T1, 31, x));
+        self.emit(Sub(T1, T1, Val::Const(1)));
+        self.emit(Mvn(T1, T1));
+        self.emit(Shl(T1, 32 - n, T1));
+        self.emit(Shr(dst, n, x));
+        self.emit(Orr(dst, dst, T1));
+    }
+
+    /// Emits a load or store
+    fn mem(&mut self, base: &str, ops: &[&'a str]) -> Result<Fl, &'static str> {
+        use Inst::*;
+        let (rt, adr) = ops.split_first().ok_or("wrong number of operands")?;
+        let rt = reg(rt)?;
+        match adr {
+            [x] if base == "ldr" && x.starts_with('=') => {
+                self.mov_expr(rt, expr(&x[1..])?);
+                return Ok(Fl::Clobber);
+            }
+            [x] if base == "ldr" && !x.starts_with('[') => {
+                let e = expr(x)?;
+                let sym = e.sym.ok_or("expected a memory operand")?;
+                self.emit_ref(Mov(rt, Val::Const(0)), Ref::Pool(sym, e.off));
+                return Ok(Fl::Clobber);
+            }
+            _ => {}
+        }
+        let (first, post) = adr.split_first().ok_or("wrong number of operands")?;
+        let (first, wb) = match first.strip_suffix('!') {
+            Some(x) => (x.trim_end(), true),
+            None => (*first, false),
+        };
+        let inner = first
+            .strip_prefix('[')
+            .and_then(|x| x.strip_suffix(']'))
+            .ok_or("expected a memory operand")?;
+        let inner = operands(inner);
+        let (rn, pre) = inner.split_first().unwrap();
+        let rn = reg(rn)?;
+        if wb && (pre.is_empty() || !post.is_empty()) || !pre.is_empty() && !post.is_empty() {
+            return Err("unsupported addressing mode");
+        }
+        // Word accesses truncate the address, so an unaligned constant
+        // offset is most likely a mistake
+        let word = matches!(base, "ldr" | "str");
+        let offset = |this: &mut Self, ops| match this.op2(ops)? {
+            Val::Const(x) if word && x % 4 != 0 => Err("unaligned offset of a word access"),
+            v => Ok(v),
+        };
+        // Byte address of the access
+        let mut a = rn;
+        if !pre.is_empty() {
+            let v = offset(self, pre)?;
+            self.emit(Add(T1, rn, v));
+            a = T1;
+        }
+        if word {
+            self.emit(Shr(T2, 2, a));
+            a = T2;
+        }
+        self.emit(match base {
+            "ldr" => Ldr(rt, a),
+            "ldrb" => Ldrb(rt, a),
+            "ldrh" => Ldrh(rt, a),
+            "str" => Str(a, rt),
+            "strb" => Strb(a, rt),
+            _ => Strh(a, rt),
+        });
+        if wb {
+            self.emit(Mov(rn, Val::Reg(T1)));
+        }
+        if !post.is_empty() {
+            let v = offset(self, post)?;
+            self.emit(Add(rn, rn, v));
+        }
+        let writes = base.starts_with("ldr") || wb || !post.is_empty();
+        Ok(if writes { Fl::Clobber } else { Fl::Keep })
+    }
+
+    /// Emits a push or pop of the registers in the list ops[0], where
+    /// the lowest register is at the lowest address
+    fn push_pop(&mut self, base: &str, ops: &[&str]) -> Result<(), &'static str> {
+        use Inst::*;
+        let [list] = ops else {
+            return Err("wrong number of operands");
+        };
+        let list = list
+            .strip_prefix('{')
+            .and_then(|x| x.strip_suffix('}'))
+            .ok_or("expected a register list")?;
+        let mut rs = vec![];
+        for x in operands(list) {
+            let (lo, hi) = x.split_once('-').unwrap_or((x, x));
+            rs.extend(arm_reg(lo.trim())?..=arm_reg(hi.trim())?);
+        }
+        rs.sort();
+        rs.dedup();
+        if rs.is_empty() || base == "push" && rs.contains(&15) {
+            return Err("expected a register list");
+        }
+        let n = Word::try_from(rs.len()).unwrap();
+        if base == "push" {
+            self.emit(Sub(SP, SP, Val::Const(4 * n)));
+        }
+        self.emit(Shr(T2, 2, SP));
+        for (k, r) in rs.iter().enumerate() {
+            if k > 0 {
+                self.emit(Add(T2, T2, Val::Const(1)));
+            }
+            // pc is popped into T0, and branched to
+            let r = if *r == 15 { T0 } else { r + 1 };
+            self.emit(match base {
+                "push" => Str(T2, r),
+                _ => Ldr(r, T2),
+            });
+        }
+        if base == "pop" {
+            self.emit(Add(SP, SP, Val::Const(4 * n)));
+            if rs.contains(&15) {
+                self.emit(B(None, Val::Reg(T0)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the flags after the instructions from start of an
+    /// instruction without condition
+    fn update_flags(&mut self, fl: Fl, start: usize) {
+        use Inst::*;
+        match fl {
+            Fl::Set { cv, redo } => {
+                let redo = if redo {
+                    self.code[start..].to_vec()
+                } else {
+                    vec![]
+                };
+                self.flags = Flags {
+                    valid: true,
+                    cv,
+                    redo,
+                }
+            }
+            Fl::Keep => {
+                let stores = |i: &Inst| matches!(i, Str(..) | Strb(..) | Strh(..));
+                if !self.code[start..].iter().all(stores) {
+                    self.flags.valid = false;
+                }
+            }
+            Fl::Clobber => self.flags = Flags::default(),
+        }
+    }
+
+    /// Makes the flags hold for a condition cc on the next instruction
+    fn use_flags(&mut self, cc: CondCode) -> Result<(), &'static str> {
+        use CondCode::*;
+        if !self.flags.valid && self.flags.redo.is_empty() {
+            return Err("condition flags were overwritten by a previous instruction");
+        }
+        if !self.flags.cv && !matches!(cc, Eq | Ne | Mi | Pl) {
+            return Err("condition on C or V after a logical instruction");
+        }
+        if !self.flags.valid {
+            for i in self.flags.redo.clone() {
+                self.emit(i);
+            }
+            self.flags.valid = true;
+        }
+        Ok(())
+    }
+
+    /// Returns whether an instruction with condition cc is part of the
+    /// current block
+    fn continue_block(&mut self, cc: CondCode) -> bool {
+        let Some(Block { cc: cc_, skip, end }) = self.block else {
+            return false;
+        };
+        if cc == cc_ && end.is_none() {
+            return true;
+        }
+        if cc != inverse(cc_) {
+            return false;
+        }
+        if end.is_none() {
+            self.block = Some(Block {
+                cc: cc_,
+                skip,
+                end: Some(self.code.len()),
+            });
+            self.emit(Inst::B(None, Val::Const(0)));
+            self.patch(skip);
+        }
+        true
+    }
+
+    fn close_block(&mut self) {
+        if let Some(b) = self.block.take() {
+            self.patch(b.end.unwrap_or(b.skip));
+        }
+    }
+
+    /// Makes the branch at i branch to the next instruction
+    fn patch(&mut self, i: usize) {
+        let pc = self.pc();
+        if let Inst::B(_, v) = &mut self.code[i] {
+            *v = Val::Const(pc);
+        }
+    }
+
+    fn eval(&self, e: &Expr) -> Result<Word, &'static str> {
+        let x = match e.sym {
+            Some(s) => *self.syms.get(s).ok_or("undefined symbol")?,
+            None => 0,
+        };
+        Ok(x.wrapping_add(e.off as Word))
+    }
+
+    fn resolve(&self, r: &Ref) -> Result<Word, &'static str> {
+        match r {
+            Ref::Val(e, part) => {
+                let x = self.eval(e)?;
+                Ok(match part {
+                    Part::All => x,
+                    Part::Lo16 => x & 0xFFFF,
+                    Part::Hi16 => x & 0xFFFF_0000,
+                })
+            }
+            Ref::Pool(sym, off) => {
+                let k = *self.pool_labels.get(sym).ok_or("not a label of a .word")?;
+                if off % 4 != 0 {
+                    return Err("unaligned literal");
+                }
+                let k = usize::try_from(off / 4).ok().map(|off| k + off);
+                let e = k.and_then(|k| self.pool.get(k));
+                self.eval(e.ok_or("not a label of a .word")?)
+            }
+        }
+    }
+
+    /// Resolves the symbols, and returns the program
+    fn finish(mut self) -> Res<Asm> {
+        use Inst::*;
+        self.close_block();
+        if !self.syms.contains_key("main") {
+            return Err((0, "no main function"));
+        }
+        for (i, r, line) in std::mem::take(&mut self.refs) {
+            let x = self.resolve(&r).map_err(|e| (line, e))?;
+            match &mut self.code[i] {
+                B(_, v) | Bl(v) | Mov(_, v) => *v = Val::Const(x),
+                _ => unreachable!(),
+            }
+        }
+        let len = self.data.len().next_multiple_of(4);
+        self.data.resize(len, 0);
+        for (k, e, line) in std::mem::take(&mut self.data_refs) {
+            let x = self.eval(&e).map_err(|e| (line, e))?;
+            self.data[k..k + 4].copy_from_slice(&x.to_le_bytes());
+        }
+        // Words are little endian, as in ARM
+        let words = self.data.chunks(4);
+        let words = words.map(|b| Word::from_le_bytes(b.try_into().unwrap()));
+        let prog = Prog {
+            code: self.code,
+            data: (ADR_DATA / 4..).zip(words).collect(),
+            regions: vec![],
+        };
+        Ok(Asm {
+            prog,
+            lines: self.lines,
+            labels: self.labels,
+        })
+    }
+}
+
+/// Returns the base mnemonic, whether it sets the flags (the S
+/// suffix) and the condition of the mnemonic op, e.g addseq
+fn mnemonic(op: &str) -> Option<(&'static str, bool, Option<CondCode>)> {
+    let parse = |base: &'static str, s_ok: bool| {
+        let rest = op.strip_prefix(base)?;
+        let s_cc = rest.strip_prefix('s').or(rest.strip_suffix('s'));
+        match (cond(rest), s_cc.and_then(cond)) {
+            (Some(cc), _) => Some((base, false, cc)),
+            (None, Some(cc)) if s_ok => Some((base, true, cc)),
+            _ => None,
+        }
+    };
+    let ops = S_OPS.iter().map(|b| (*b, true));
+    let mut ops = ops.chain(OPS.iter().map(|b| (*b, false)));
+    ops.find_map(|(b, s_ok)| parse(b, s_ok))
+}
+
+/// Parses a condition, where "" and "al" are no condition
+fn cond(s: &str) -> Option<Option<CondCode>> {
+    use CondCode::*;
+    let cc = match s {
+        "" | "al" => return Some(None),
+        "eq" => Eq,
+        "ne" => Ne,
+        "cs" | "hs" => Hs,
+        "cc" | "lo" => Lo,
+        "mi" => Mi,
+        "pl" => Pl,
+        "vs" => Vs,
+        "vc" => Vc,
+        "hi" => Hi,
+        "ls" => Ls,
+        "ge" => Ge,
+        "lt" => Lt,
+        "gt" => Gt,
+        "le" => Le,
+        _ => return None,
+    };
+    Some(Some(cc))
+}
+
+fn inverse(cc: CondCode) -> CondCode {
+    use CondCode::*;
+    match cc {
+        Eq => Ne,
+        Ne => Eq,
+        Hs => Lo,
+        Lo => Hs,
+        Mi => Pl,
+        Pl => Mi,
+        Vs => Vc,
+        Vc => Vs,
+        Hi => Ls,
+        Ls => Hi,
+        Ge => Lt,
+        Lt => Ge,
+        Gt => Le,
+        Le => Gt,
+    }
+}
+
+/// Parses an ARM register, and returns its number
+fn arm_reg(s: &str) -> Result<u8, &'static str> {
+    let s = s.to_lowercase();
+    let r = match s.as_str() {
+        "sb" => 9,
+        "sl" => 10,
+        "fp" => 11,
+        "ip" => 12,
+        "sp" => 13,
+        "lr" => 14,
+        "pc" => 15,
+        _ => s
+            .strip_prefix('r')
+            .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|n| n.parse().ok())
+            .filter(|r| *r < 16)
+            .ok_or("expected a register")?,
+    };
+    Ok(r)
+}
+
+/// Parses an ARM register other than pc, and returns the MiniRAM
+/// register
+fn reg(s: &str) -> Result<Reg, &'static str> {
+    match arm_reg(s)? {
+        15 => Err("unsupported use of pc"),
+        r => Ok(r + 1),
+    }
+}
+
+fn is_pc(s: &str) -> bool {
+    arm_reg(s) == Ok(15)
+}
+
+fn is_imm(s: &str) -> bool {
+    s.starts_with('#') || number(s).is_some()
+}
+
+fn is_shift(s: &str) -> bool {
+    let s = s.to_lowercase();
+    ["lsl ", "lsr ", "asr ", "ror "]
+        .iter()
+        .any(|k| s.starts_with(k))
+}
+
+fn is_sym(s: &str) -> bool {
+    let mut cs = s.chars();
+    cs.next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || "_.$".contains(c))
+        && cs.all(|c| c.is_ascii_alphanumeric() || "_.$".contains(c))
+}
+
+/// Parses a number of at most the given bits, where negative numbers
+/// are in two's complement
+fn int(s: &str, bits: u32) -> Result<Word, &'static str> {
+    let x = number(s.strip_prefix('#').unwrap_or(s)).ok_or("expected a number")?;
+    if x < -(1 << (bits - 1)) || x >= 1 << bits {
+        return Err("number out of range");
+    }
+    Ok((x & ((1 << bits) - 1)) as Word)
+}
+
+/// Parses a number, a symbol or a symbol plus or minus a number
+fn expr(s: &str) -> Result<Expr<'_>, &'static str> {
+    let s = s.trim();
+    if number(s).is_some() {
+        let off = i128::from(int(s, 32)?);
+        return Ok(Expr { sym: None, off });
+    }
+    let (sym, off) = match s.rfind(['+', '-']) {
+        Some(k) if k > 0 => (s[..k].trim(), number(s[k..].trim())),
+        _ => (s, Some(0)),
+    };
+    match off {
+        Some(off) if is_sym(sym) => Ok(Expr {
+            sym: Some(sym),
+            off,
+        }),
+        _ => Err("expected a symbol"),
+    }
+}
+
+/// Parses a string literal, with the escapes of C
+fn string(s: &str) -> Result<Vec<u8>, &'static str> {
+    let s = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or("expected a string")?;
+    let mut bytes = vec![];
+    let mut cs = s.chars().peekable();
+    while let Some(c) = cs.next() {
+        if c != '\\' {
+            bytes.extend(c.to_string().as_bytes());
+            continue;
+        }
+        let b = match cs.next().ok_or("expected an escape")? {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            '\\' => b'\\',
+            '"' => b'"',
+            c @ '0'..='7' => {
+                // Up to 3 octal digits
+                let mut x = c.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match cs.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => x = 8 * x + d,
+                        None => break,
+                    }
+                    cs.next();
+                }
+                u8::try_from(x).map_err(|_| "escape out of range")?
+            }
+            _ => return Err("unsupported escape"),
+        };
+        bytes.push(b);
+    }
+    Ok(bytes)
+}
+
+/// Returns s up to a comment, i.e '@' or "//" outside a string
+fn strip_comment(s: &str) -> &str {
+    let (mut in_str, mut escaped) = (false, false);
+    for (k, c) in s.char_indices() {
+        if in_str {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_str = true,
+            '@' => return &s[..k],
+            '/' if s[k..].starts_with("//") => return &s[..k],
+            _ => {}
+        }
+    }
+    s
+}
+
+/// Splits s at the commas outside brackets, braces and strings
+fn operands(s: &str) -> Vec<&str> {
+    let mut ops = vec![];
+    let (mut depth, mut in_str, mut escaped, mut start) = (0, false, false, 0);
+    for (k, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_str => escaped = true,
+            '"' => in_str = !in_str,
+            '[' | '{' if !in_str => depth += 1,
+            ']' | '}' if !in_str => depth -= 1,
+            ',' if !in_str && depth == 0 => {
+                ops.push(s[start..k].trim());
+                start = k + 1;
+            }
+            _ => {}
+        }
+    }
+    ops.push(s[start..].trim());
+    ops
+}
+
+/// Parses N registers
+fn regs<const N: usize>(ops: &[&str]) -> Result<[Reg; N], &'static str> {
+    let ops: &[&str; N] = ops.try_into().map_err(|_| "wrong number of operands")?;
+    let mut rs = [0; N];
+    for (r, x) in rs.iter_mut().zip(ops) {
+        *r = reg(x)?;
+    }
+    Ok(rs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::translate;
+    use crate::miniram::interpreter::interpret;
+    use crate::miniram::reduction::{encode_witness, generate_circuit, Arch};
+    use backend::ProofCtx;
+    use utils::circuit::eval32;
+
+    #[test]
+    fn mul_eq() {
+        let src = include_str!("../arm/mul_eq.s");
+        let p = &translate(src).unwrap().prog;
+        for args in [vec![2, 2, 4], vec![1, 7, 7], vec![31, 65, 31 * 65]] {
+            let res = interpret(p, args, vec![], vec![], Some(1000));
+            assert_eq!(res.unwrap().0, 0);
+        }
+        let res = interpret(p, vec![3, 4, 11], vec![], vec![], Some(1000));
+        assert_eq!(res.unwrap().0, 1);
+    }
+
+    /// Returns 0, or the number of the first check failing (in r7)
+    #[test]
+    fn features() {
+        let src = r#"
+            .data
+            .align  2
+        tab:    .word 3, 5, -1, tab
+        bytes:  .byte 1, 2, 0xFF
+            .align  1
+        half:   .short 0x1234
+        str:    .asciz "ab\n"
+            .bss
+            .align  2
+        buf:    .space 8
+
+            .text
+            .global main
+        main:
+            push    {r4-r7, lr}
+            @ 1: sum of tab[0..3] by post-indexed loads
+            movs    r7, #1
+            ldr     r1, =tab
+            movs    r0, #0
+            movs    r2, #3
+        .Lsum:
+            ldr     r3, [r1], #4
+            add     r0, r0, r3
+            subs    r2, r2, #1
+            bne     .Lsum
+            cmp     r0, #7
+            bne     .Lfail
+            @ 2: literal pool
+            movs    r7, #2
+            ldr     r3, [r1]
+            ldr     r4, .Lpool
+            cmp     r3, r4
+            bne     .Lfail
+            ldr     r4, .Lpool+4
+            cmp     r1, r4
+            bne     .Lfail
+            @ 3: bytes and halfwords
+            movs    r7, #3
+            ldr     r1, =bytes
+            ldrb    r2, [r1, #2]
+            ldrb    r3, [r1]
+            add     r2, r2, r3
+            ldr     r1, =half
+            ldrh    r3, [r1]
+            movw    r4, #0x1134
+            add     r2, r2, r4
+            cmp     r2, r3
+            bne     .Lfail
+            ldr     r1, =str
+            ldrb    r2, [r1, #2]
+            cmp     r2, #10
+            bne     .Lfail
+            @ 4: stores with writeback
+            movs    r7, #4
+            ldr     r1, =buf
+            mov     r2, #0xAB
+            strb    r2, [r1, #1]!
+            movw    r3, #:lower16:buf
+            movt    r3, #:upper16:buf
+            adds    r3, r3, #1
+            cmp     r1, r3
+            bne     .Lfail
+            ldr     r3, =buf
+            ldr     r3, [r3]
+            cmp     r3, #0xAB00
+            bne     .Lfail
+            @ 5: signed arithmetic and shifted operands
+            movs    r7, #5
+            mvn     r1, #7
+            asr     r2, r1, #2
+            cmn     r2, #2
+            bne     .Lfail
+            sxtb    r3, r1
+            cmp     r3, r1
+            bne     .Lfail
+            cmp     r1, #0
+            bge     .Lfail
+            movs    r1, #3
+            add     r2, r1, r1, lsl #2
+            cmp     r2, #15
+            bne     .Lfail
+            @ 6: conditional execution
+            movs    r7, #6
+            movs    r1, #5
+            cmp     r1, #3
+            ite     gt
+            movgt   r2, #1
+            movle   r2, #2
+            cmp     r2, #1
+            addeq   r2, r2, #10
+            cmp     r2, #11
+            bne     .Lfail
+            @ 7: calls, multiplication and division
+            movs    r7, #7
+            mov     r0, #0x10000
+            mov     r1, #0x30000
+            bl      mulhi
+            cmp     r0, #3
+            bne     .Lfail
+            movs    r1, #7
+            movs    r2, #3
+            udiv    r3, r1, r2
+            mls     r3, r3, r2, r1
+            cmp     r3, #1
+            bne     .Lfail
+            @ 8: comparison repeated for consecutive branches
+            movs    r7, #8
+            movs    r0, #2
+            cmp     r0, #2
+            str     r0, [sp, #-4]
+            blo     .Lfail
+            bhi     .Lfail
+            bne     .Lfail
+            movs    r0, #0
+            pop     {r4-r7, pc}
+        .Lfail:
+            mov     r0, r7
+            pop     {r4-r7, pc}
+        .Lpool:
+            .word   tab
+            .word   tab+12
+
+        mulhi:
+            umull   r2, r0, r0, r1
+            bx      lr
+        "#;
+        let asm = translate(src).unwrap();
+        let res = interpret(&asm.prog, vec![], vec![], vec![], Some(1000));
+        assert_eq!(res.unwrap().0, 0);
+    }
+
+    /// Products of at least 2^32 through the reduction and the prover
+    /// and verifier
+    #[test]
+    fn mul_prove() {
+        let src = "
+        main:
+            ldr     r1, [r0]
+            ldr     r2, [r0, #4]
+            mul     r3, r1, r2
+            umull   r0, r12, r1, r2
+            mla     r3, r1, r2, r3
+            mls     r0, r1, r2, r0
+            add     r0, r0, r3
+            add     r0, r0, r12
+            bx      lr
+        ";
+        let p = &translate(src).unwrap().prog;
+        let args = vec![0x10000, 0x30003];
+        let (res, _, trace) = interpret(p, args.clone(), vec![], vec![], Some(100)).unwrap();
+        assert_eq!(res, 0x60003);
+        let t = trace.len();
+        let c = generate_circuit(p, &[], 0, t, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let (w, outputs) = encode_witness(p, args, vec![], vec![], t, Arch::Harvard, ctx).unwrap();
+        assert_eq!(outputs[0], 0x60003);
+        assert!(eval32(&c, w.clone()).iter().all(|x| *x == 0));
+        assert!(crate::runners::prove_verify(c, w, outputs));
+    }
+
+    #[test]
+    fn shift_by_register() {
+        // Only the bottom byte of the amount is used
+        let amts = [0, 1, 31, 32, 33, 255, 256, 0x120];
+        for (ins, lsl) in [
+            ("lsl r0, r1, r2", true),
+            ("lsr r0, r1, r2", false),
+            ("mov r0, r1, lsl r2", true),
+            ("lsr r2, r1, r2\n mov r0, r2", false),
+        ] {
+            let src = format!("main: ldr r1, [r0]\n ldr r2, [r0, #4]\n {ins}\n bx lr");
+            let p = &translate(&src).unwrap().prog;
+            for amt in amts {
+                let x = 0x8000_0001;
+                let res = match (amt & 0xFF, lsl) {
+                    (32.., _) => 0,
+                    (n, true) => x << n,
+                    (n, false) => x >> n,
+                };
+                let res_ = interpret(p, vec![x, amt], vec![], vec![], Some(100));
+                assert_eq!(res_.unwrap().0, res, "{ins} by {amt}");
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for (src, line, msg) in [
+            ("main: sdiv r0, r0, r1", 1, "unsupported instruction"),
+            ("main: bx lr\n.comm x, 4, 4", 2, "unsupported directive"),
+            ("main: bl memcpy", 1, "undefined symbol"),
+            ("main: mov r0, pc", 1, "unsupported use of pc"),
+            (
+                "main: asr r0, r0, r1",
+                1,
+                "ASR by a register is not supported",
+            ),
+            (
+                "main: ldr r0, [r1, #4], #4",
+                1,
+                "unsupported addressing mode",
+            ),
+            (
+                "main: ldr r0, [r0, #2]",
+                1,
+                "unaligned offset of a word access",
+            ),
+            (
+                "main: str r0, [r1], #-1",
+                1,
+                "unaligned offset of a word access",
+            ),
+            (".data\nmain: bx lr", 2, "instruction in a data section"),
+            ("f: bx lr", 0, "no main function"),
+            (
+                "main: cmp r0, #0\nadd r0, r0, #1\nbeq main",
+                3,
+                "condition flags were overwritten by a previous instruction",
+            ),
+            (
+                "main: ands r0, r0, #1\nbhi main",
+                2,
+                "condition on C or V after a logical instruction",
+            ),
+        ] {
+            assert_eq!(translate(src).unwrap_err(), (line, msg), "{src}");
+        }
+    }
+}
@@ -0,0 +1,691 @@
+use crate::runners::run_p;
+use crate::runners::run_v;
+use crate::runners::run_vole;
+use args::Args;
+use args::ArgsError;
+use getopts::Occur;
+use std::env;
+use std::process::exit;
+use utils::sha256;
+
+use backend::ProofCtx;
+use utils::circuit::builder::Res as Circuit;
+use utils::circuit::circuits;
+
+use crate::arm;
+use crate::miniram::asm::assemble;
+use crate::miniram::encode;
+use crate::miniram::interpreter::interpret;
+use crate::miniram::lang::n_reg;
+use crate::miniram::lang::Prog;
+use crate::miniram::lang::Word;
+use crate::miniram::lang::N_REGS;
+use crate::miniram::object;
+use crate::miniram::programs;
+use crate::miniram::programs::compress;
+use crate::miniram::programs::verify_compress;
+use crate::miniram::reduction::encode_witness;
+use crate::miniram::reduction::generate_circuit;
+use crate::miniram::reduction::generate_circuit_private;
+use crate::miniram::reduction::program_digest;
+use crate::miniram::reduction::Arch;
+
+const PROGRAM_DESC: &str = "VOLE-based ZK proof of correct MiniRAM executions";
+const PROGRAM_NAME: &str = "miniram-zk";
+
+/// Options:
+///  -p, --party:
+///  --ip-other: IP of the other party
+///  --ip-vole:  IP of trusted VOLE dealer
+pub fn main() {
+    // parse input ARM prog
+    // translate ARM prog to MiniRAM
+    // encode witness
+    //
+    match parse(env::args()) {
+        Ok(ParseRes {
+            party,
+            port,
+            port_vole,
+            prog,
+            t,
+            circuit,
+            run,
+            arg,
+            arch,
+            n_out,
+            expected,
+            disassemble,
+            object,
+            program,
+        }) => {
+            println!("Successfully parsed args");
+
+            if let Some(party) = party {
+                let deterministic = false;
+                let mut ctx = if deterministic {
+                    ProofCtx::new_deterministic()
+                } else {
+                    ProofCtx::new_random()
+                };
+
+                match party.as_str() {
+                    "prover" => {
+                        assert!(port_vole.is_some());
+                        let (c, w) = if prog.is_some() & t.is_some() {
+                            let prog = prog.unwrap();
+                            let t = t.unwrap();
+                            let (prog, args, public) =
+                                test_prog(prog.as_str(), arg, party.as_str());
+                            let (w, outputs) = encode_witness(
+                                &prog,
+                                args,
+                                public.clone(),
+                                vec![],
+                                t,
+                                arch,
+                                &mut ctx,
+                            )
+                            .unwrap(); // todo: handle?
+                            if outputs.len() != n_out + 1 {
+                                println!("err: program output {} values", outputs.len() - 1);
+                                exit(1);
+                            }
+                            println!("Public outputs: {outputs:?}");
+                            if arch == Arch::Private {
+                                let digest = program_digest(&prog).map(|w| format!("{w:08x}"));
+                                println!(
+                                    "Private program: --program-digest {} --n-instr {} --n-reg {}",
+                                    digest.concat(),
+                                    prog.code.len(),
+                                    n_reg(&prog)
+                                );
+                            }
+                            ctx.start_time("generate circuit");
+                            let c = generate_circuit(&prog, &public, n_out, t, arch);
+                            ctx.stop_time();
+                            (c, w)
+                        } else if let Some(circuit) = circuit {
+                            test_circuit(circuit.as_str(), arg)
+                        } else {
+                            println!("err: want a) prog and time-bound or b) circuit ");
+                            exit(1);
+                        };
+                        print_circuit_stats(&c);
+                        run_p(port.unwrap(), port_vole.unwrap(), c, w, ctx)
+                    }
+                    "verifier" => {
+                        assert!(port_vole.is_some());
+                        let c = if let (Arch::Private, None, Some(t)) = (arch, &prog, t) {
+                            // Only the digest and size of the program are known
+                            let (digest, n_instr, n_reg) = program.unwrap_or_else(|| {
+                                println!("err: want --program-digest, --n-instr and --n-reg");
+                                exit(1);
+                            });
+                            let io = (&[][..], n_out);
+                            ctx.start_time("generate circuit");
+                            let c =
+                                generate_circuit_private(&digest, n_instr, n_reg, &[], &[], io, t);
+                            ctx.stop_time();
+                            c
+                        } else if prog.is_some() & t.is_some() {
+                            let prog = prog.unwrap();
+                            let t = t.unwrap();
+                            let (prog, _, public) = test_prog(prog.as_str(), arg, party.as_str());
+                            ctx.start_time("generate circuit");
+                            let c = generate_circuit(&prog, &public, n_out, t, arch);
+                            ctx.stop_time();
+                            c
+                        } else if let Some(circuit) = circuit {
+                            test_circuit(circuit.as_str(), arg).0
+                        } else {
+                            println!("err: want a) prog and time-bound or b) circuit ");
+                            exit(1);
+                        };
+                        run_v(port.unwrap(), port_vole.unwrap(), c, expected, ctx)
+                    }
+                    "vole" => run_vole(port.unwrap(), ctx),
+                    _ => {
+                        println!("don't understand: {}", party);
+                        exit(1);
+                    }
+                }
+                .unwrap();
+            } else if let Some(prog) = run {
+                match prog.as_str() {
+                    "verify_compress" => {
+                        let arg = arg.unwrap();
+                        let mut arg = arg.split(',');
+                        let msg = arg.next().unwrap();
+                        let mac = arg.next().unwrap();
+                        let msg_ = sha256::pad(msg);
+                        let mac_ = sha256::parse_mac(mac);
+                        let n_rounds = msg_.len() as u32;
+                        let prog = &verify_compress(n_rounds);
+                        println!("Running (verify_compress({mac}))({msg}):");
+                        let (res, _, _) =
+                            interpret(prog, msg_, mac_[..8].to_vec(), vec![], t).unwrap();
+                        println!("res={res}");
+                    }
+                    "compress" => {
+                        let arg = arg.unwrap();
+                        let arg_ = sha256::pad(&arg);
+                        let prog = &compress(true, arg_.len() as u32);
+                        println!("Running compress({arg}):");
+                        let (_, _, _) = interpret(prog, arg_, vec![], vec![], t).unwrap();
+                        println!();
+                    }
+                    path if is_file(path) => {
+                        let (prog, _) = &load_file(path);
+                        let args = arg.map(|arg| parse_words(&arg)).unwrap_or_default();
+                        println!("Running {path}:");
+                        let (res, outputs, _) = interpret(prog, args, vec![], vec![], t).unwrap();
+                        println!("res={res} outputs={outputs:?}");
+                    }
+                    _ => todo!(),
+                }
+            } else if let (Some(path), Some(prog)) = (&object, &prog) {
+                let (prog, labels) = match is_file(prog) {
+                    true => load_file(prog),
+                    false => (test_prog(prog, arg, "verifier").0, vec![]),
+                };
+                let bytes = object::write(&prog, &labels);
+                std::fs::write(path, &bytes).unwrap();
+                println!("Wrote {path} with hash {}", object::hash(&bytes));
+            } else if let (true, Some(prog)) = (disassemble, prog) {
+                let (prog, _, _) = test_prog(prog.as_str(), arg, "verifier");
+                print!("{}", encode::disassemble(&encode::encode(&prog)).unwrap());
+            } else {
+                println!("--run, --party, --object or --disassemble must be set");
+                exit(1);
+            }
+        }
+        Err(error) => {
+            println!("{}", error);
+            exit(1);
+        }
+    };
+}
+
+/// Returns (prog, args, public) where args is only known to the
+/// prover and public is the public tape of prog.
+fn test_prog(prog: &str, arg: Option<String>, party: &str) -> (Prog, Vec<Word>, Vec<Word>) {
+    match prog {
+        "mul_eq" => {
+            let prog = programs::mul_eq();
+            let args = vec![2, 2, 4];
+            (prog, args, vec![])
+        }
+        "mul_native_eq" => {
+            let prog = programs::mul_native_eq();
+            let args = vec![2, 2, 4];
+            (prog, args, vec![])
+        }
+        "const0" => {
+            let prog = programs::const_0();
+            let args = vec![];
+            (prog, args, vec![])
+        }
+        "shr" => {
+            let prog = programs::shr();
+            let args = vec![];
+            (prog, args, vec![])
+        }
+        "rotr" => {
+            let prog = programs::rotr();
+            let args = vec![];
+            (prog, args, vec![])
+        }
+        "overflowing_add" => {
+            let prog = programs::overflowing_add();
+            let args = vec![];
+            (prog, args, vec![])
+        }
+        "verify_compress" => {
+            let (mac, witness, n_rounds) = match party {
+                "prover" => {
+                    let arg = arg.unwrap();
+                    let mut arg = arg.split(',');
+                    let msg = arg.next().unwrap().to_string();
+                    let mac = arg.next().unwrap().to_string();
+                    let msg_ = sha256::pad(&msg);
+                    let n_rounds = msg_.len() as u32;
+                    (mac, msg_, n_rounds)
+                }
+                "verifier" => {
+                    let arg = arg.unwrap();
+                    let mut arg = arg.split(',');
+                    let mac = arg.next().unwrap().to_string();
+                    let n_rounds = arg.next().unwrap();
+                    (mac, vec![], n_rounds.parse::<u32>().unwrap() * 16)
+                }
+                _ => {
+                    panic!("unreachable")
+                }
+            };
+            let mac = sha256::parse_mac(&mac);
+            let prog = programs::verify_compress(n_rounds);
+            (prog, witness, mac[..8].to_vec())
+        }
+        path if is_file(path) => {
+            let (prog, _) = load_file(path);
+            let args = match (party, arg) {
+                ("prover", Some(arg)) => parse_words(&arg),
+                _ => vec![],
+            };
+            (prog, args, vec![])
+        }
+        _ => {
+            println!("don't understand: {}", prog);
+            exit(1);
+        }
+    }
+}
+
+/// Returns whether prog is the path of a file to load the program
+/// from, rather than the name of a test program
+fn is_file(prog: &str) -> bool {
+    [".mram", ".mro", ".s"]
+        .iter()
+        .any(|ext| prog.ends_with(ext))
+}
+
+/// Loads the program and its labels from the file at path, which is
+/// either MiniRAM assembly (.mram, see asm::assemble), an object file
+/// (.mro, see object::read) or ARM assembly (.s, see arm::translate)
+fn load_file(path: &str) -> (Prog, object::Labels) {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| {
+        println!("err: can't read {path}: {e}");
+        exit(1);
+    });
+    if path.ends_with(".mro") {
+        println!("Program hash: {}", object::hash(&bytes));
+        return object::read(&bytes).unwrap_or_else(|e| {
+            println!("err: {path}: {e}");
+            exit(1);
+        });
+    }
+    let src = String::from_utf8_lossy(&bytes);
+    let asm = if path.ends_with(".s") {
+        arm::translate(&src)
+    } else {
+        assemble(&src)
+    };
+    match asm {
+        Ok(asm) => (asm.prog, asm.labels),
+        Err((line, msg)) => {
+            println!("err: {path}:{line}: {msg}");
+            exit(1);
+        }
+    }
+}
+
+/// Parses comma separated words, e.g the arguments of a program
+fn parse_words(s: &str) -> Vec<Word> {
+    s.split(',')
+        .map(|x| x.trim().parse().expect("expected a word"))
+        .collect()
+}
+
+fn test_circuit(circuit: &str, arg: Option<String>) -> (Circuit<u32>, Vec<u32>) {
+    match circuit {
+        "add_eq_42" => {
+            let c = circuits::add_eq_42();
+            let w = vec![21, 21];
+            (c, w)
+        }
+        "add_eq" => {
+            let c = circuits::add_eq();
+            let w = vec![21, 21, 42];
+            (c, w)
+        }
+        "mul_eq" => {
+            let c = circuits::mul_eq();
+            let w = vec![2, 2, 4];
+            (c, w)
+        }
+        "mul_const" => {
+            let c = circuits::mul_const();
+            let w = vec![42, 42 * 42];
+            (c, w)
+        }
+        "mul_mul_eq" => {
+            let c = circuits::mul_mul_eq();
+            let w = vec![2, 2, 7, 28];
+            (c, w)
+        }
+        "pow_eq" => {
+            let c = circuits::pow();
+            let w = vec![2, 3, 8];
+            (c, w)
+        }
+        "select_eq" => {
+            let c = circuits::select_eq();
+            let w = vec![0, 0, 1];
+            (c, w)
+        }
+        "select_eq2" => {
+            let c = circuits::select_eq2();
+            let w = vec![2, 1337, 1, 0, 42];
+            (c, w)
+        }
+        "select_const" => {
+            let c = circuits::select_const(0, 1);
+            let w = vec![0];
+            (c, w)
+        }
+        "select_const_vec" => {
+            let c = circuits::select_const_vec(&[0, 1, 2, 3, 0, 5]);
+            let w = vec![4];
+            (c, w)
+        }
+        "encode4" => {
+            let c = circuits::encode4(1 + 2 + 8);
+            let w = vec![1, 1, 0, 1];
+            (c, w)
+        }
+        "decode32" => {
+            let c = circuits::decode32();
+            let w = vec![0];
+            (c, w)
+        }
+        "w_all_eq_but_one" => {
+            let c = circuits::check_all_eq_but_one();
+            let w = vec![1, 43, 43, 2, 3];
+            (c, w)
+        }
+        "decode32_128_bit" => {
+            let c = circuits::add_decode32();
+            let w = vec![1 << 31, 1 << 31];
+            (c, w)
+        }
+        "add" => {
+            let arg = arg.unwrap();
+            let mut arg = arg.split(',');
+            let x = arg.next().unwrap().parse::<u32>().unwrap();
+            let y = arg.next().unwrap().parse::<u32>().unwrap();
+            let c = circuits::add();
+            let w = vec![x, y];
+            (c, w)
+        }
+        _ => {
+            println!("don't understand: {}", circuit);
+            exit(1);
+        }
+    }
+}
+
+fn print_circuit_stats<T>(c: &Circuit<T>) {
+    let n_in = c.n_in;
+    let n_mul = c.n_mul;
+    let n_gates = c.gates.len();
+    let n_select = c.n_select_alt;
+    let n_select_const = c.n_select_const_alt;
+    let n_decode32 = c.n_decode32;
+    let n_check_all = c.n_check_all_eq_pairs;
+    let n_consts = c.consts.len();
+    let n_out = c.n_out;
+    let n_openings = c.openings.len();
+    println!("Circuit ====================================");
+    println!("  number of inputs         : {n_in}");
+    println!("  number of gates          : {n_gates}");
+    println!("    - multiplication       : {n_mul}");
+    println!("    - select alts.         : {n_select}");
+    println!("    - select_const alts.   : {n_select_const}");
+    println!("    - decode32             : {n_decode32}");
+    println!("    - check all eq but one : {n_check_all}");
+    println!("    - outputs              : {n_out}");
+    println!("    - openings             : {n_openings}");
+    println!("  number of constants      : {n_consts}");
+    println!("============================================");
+}
+
+struct ParseRes {
+    party: Option<String>,
+    port: Option<u16>,
+    port_vole: Option<u16>,
+    prog: Option<String>,
+    t: Option<usize>,
+    circuit: Option<String>,
+    run: Option<String>,
+    arg: Option<String>,
+    arch: Arch,
+    n_out: usize,
+    /// Value returned and values output by the program, that the
+    /// verifier expects the proof to open
+    expected: Option<Vec<Word>>,
+    disassemble: bool,
+    object: Option<String>,
+    /// Digest, instruction count and register count of the program
+    /// of the Private mode, for the verifier
+    program: Option<([Word; 8], usize, usize)>,
+}
+
+fn parse(input: std::env::Args) -> Result<ParseRes, ArgsError> {
+    let mut args = Args::new(PROGRAM_NAME, PROGRAM_DESC);
+    args.option(
+        "p",
+        "party",
+        "Which party to execute protocol as",
+        "PARTY",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "port",
+        "Port of the prover on localhost",
+        "PROVER_PORT",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "v",
+        "vole-port",
+        "Port of the trusted VOLE dealer running on the localhost",
+        "VOLE_PORT",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "x",
+        "prog",
+        "Which test program/args, or .mram/ .mro/ .s file, to use (cannot be used with -b)",
+        "PROG",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "t",
+        "time-bound",
+        "Max steps of program to verify (must be used with -x)",
+        "TIME_BOUND",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "c",
+        "circuit",
+        "Which test circuit/ witness to use (cannot be used with -x)",
+        "CIRCUIT",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "run",
+        "Which MiniRAM program, or .mram/ .mro/ .s file, to interpret directly",
+        "PROG",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "arg",
+        "Which arguments for --run",
+        "PROG",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "outputs",
+        "Number of values output by the program (-x), besides the value returned",
+        "N_OUT",
+        Occur::Optional,
+        Some(String::from("0")),
+    );
+
+    args.option(
+        "",
+        "expect",
+        "Value returned by the program followed by its outputs, that the verifier must open (comma separated)",
+        "WORDS",
+        Occur::Optional,
+        None,
+    );
+
+    args.flag(
+        "",
+        "von-neumann",
+        "Prove execution of the program (-x) loaded into memory",
+    );
+
+    args.flag(
+        "",
+        "private-program",
+        "Like --von-neumann, but only reveal the length and digest of the program",
+    );
+
+    args.option(
+        "",
+        "program-digest",
+        "Digest of the program of --private-program, as printed by the prover (instead of -x)",
+        "DIGEST",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "n-instr",
+        "Number of instructions of the program of --program-digest",
+        "N_INSTR",
+        Occur::Optional,
+        None,
+    );
+
+    args.option(
+        "",
+        "n-reg",
+        "Number of registers of the program of --program-digest",
+        "N_REG",
+        Occur::Optional,
+        None,
+    );
+
+    args.flag(
+        "",
+        "disassemble",
+        "Print the encoded program (-x) as assembly, with the encoding of each instruction",
+    );
+
+    args.option(
+        "",
+        "object",
+        "Write the program (-x) to an object file",
+        "PATH",
+        Occur::Optional,
+        None,
+    );
+
+    args.parse(input)?;
+
+    let party = args.optional_value_of("party").unwrap();
+    let port = args.optional_value_of("port").unwrap();
+    let port_vole = args.optional_value_of("vole-port").unwrap();
+
+    let prog = args.optional_value_of("prog").unwrap();
+    let t = args.optional_value_of("time-bound").unwrap();
+    let circuit = args.optional_value_of("circuit").unwrap();
+
+    let run = args.optional_value_of("run").unwrap();
+    let arg = args.optional_value_of("arg").unwrap();
+    let n_out = args.value_of("outputs")?;
+    let expected = args
+        .optional_value_of::<String>("expect")
+        .unwrap()
+        .map(|s| parse_words(&s));
+    if expected.as_ref().is_some_and(|e| e.len() != n_out + 1) {
+        println!(
+            "err: --expect must have {} values (see --outputs)",
+            n_out + 1
+        );
+        exit(1);
+    }
+    let arch = if args.value_of("private-program")? {
+        Arch::Private
+    } else if args.value_of("von-neumann")? {
+        Arch::VonNeumann
+    } else {
+        Arch::Harvard
+    };
+    let disassemble = args.value_of("disassemble")?;
+    let object = args.optional_value_of("object").unwrap();
+    let digest: Option<String> = args.optional_value_of("program-digest").unwrap();
+    let n_instr = args.optional_value_of("n-instr").unwrap();
+    let n_reg = args.optional_value_of("n-reg").unwrap();
+    let program = match (digest, n_instr, n_reg) {
+        (Some(digest), Some(n_instr), Some(n_reg)) => Some((parse_digest(&digest), n_instr, n_reg)),
+        (None, None, None) => None,
+        _ => {
+            println!("err: want all of --program-digest, --n-instr and --n-reg");
+            exit(1);
+        }
+    };
+    if program.is_some_and(|(_, _, n_reg)| !N_REGS.contains(&n_reg)) {
+        println!("err: --n-reg must be one of {N_REGS:?}");
+        exit(1);
+    }
+
+    Ok(ParseRes {
+        party,
+        port,
+        port_vole,
+        prog,
+        t,
+        circuit,
+        run,
+        arg,
+        arch,
+        n_out,
+        expected,
+        disassemble,
+        object,
+        program,
+    })
+}
+
+/// Parses the digest of a program as printed by the prover, i.e 8
+/// words in hex, or exits if it is malformed
+fn parse_digest(s: &str) -> [Word; 8] {
+    let words = (0..8)
+        .map(|k| s.get(8 * k..8 * (k + 1)))
+        .map(|w| w.and_then(|w| Word::from_str_radix(w, 16).ok()))
+        .collect::<Option<Vec<_>>>();
+    match words {
+        Some(words) if s.len() == 64 => words.try_into().unwrap(),
+        _ => {
+            println!("err: --program-digest must be 64 hex digits");
+            exit(1);
+        }
+    }
+}
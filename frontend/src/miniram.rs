@@ -1,7 +1,10 @@
+pub mod asm;
 pub mod builder;
 pub mod encode;
 pub mod interpreter;
 pub mod lang;
+pub mod object;
+#[cfg(not(feature = "word64"))]
 pub mod reduction;
 // #[cfg(test)]
 pub mod programs;
@@ -0,0 +1,567 @@
+use std::collections::HashMap;
+
+use crate::miniram::lang::*;
+
+/// Result of assembling a program, with the line (counting from 1)
+/// and a description of the first error
+pub type Res<T> = Result<T, (usize, &'static str)>;
+
+/// A program assembled from MiniRAM assembly (see assemble)
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct Asm {
+    pub prog: Prog,
+    /// Source line of each instruction, counting from 1
+    pub lines: Vec<usize>,
+    /// Labels and their value, i.e the index of an instruction or
+    /// the address of a data word
+    pub labels: Vec<(String, Word)>,
+}
+
+/// Assembles the MiniRAM assembly src (usually a .mram file), which
+/// has a statement per line:
+///
+///   loop:  ADD r1, r1, 1   ; comment
+///
+/// A statement can be preceded by any number of labels, and anything
+/// after ';' is a comment.
+///
+/// Instructions are written as a mnemonic and comma separated
+/// operands. Mnemonics are case insensitive and named after the
+/// variants of Inst, where a shift by a register (e.g ShrR) is the
+/// shift with a register as shift amount:
+///
+///   AND/ XOR/ ADD/ SUB dst, x, y|imm
+///   ORR/ MUL/ UMULH/ UDIV/ UREM dst, x, y
+///   SHR/ SHL/ ROTR dst, x, y|imm       dst = x shifted by y
+///   MVN dst, x
+///   CMP x, y|imm
+///   TST x, y
+///   MOV dst, y|imm
+///   CSEL dst, x, y, cc
+///   LDR/ LDRB/ LDRH dst, adr           dst = mem[adr]
+///   STR/ STRB/ STRH adr, src           mem[adr] = src
+///   B[.cc] target|imm
+///   BREL[.cc] label|offset             offset is relative to pc
+///   BL target|imm
+///   RET x|imm
+///   READ dst, public|private
+///   OUT x
+///   PRINT x
+///
+/// where cc is a condition code, e.g eq or ne (see CondCode).
+/// Registers are r0, ..., r31, where r0 is also called pc and r15
+/// lr. Immediates are decimal, hexadecimal (0x) or binary (0b)
+/// numbers, possibly negative and prefixed by '#', or the name of a
+/// label or constant.
+///
+/// Directives are:
+///
+///   .equ NAME, imm                     define a constant
+///   .data adr                          start a data section at adr
+///   .word imm, ...                     data words of the section
+///   .text                              go back to the code
+///   .region start, end, r|rw           declare a memory region
+///
+/// Labels in the code have the index of the next instruction as
+/// value, and labels in a data section the address of the next
+/// data word. Constants and the address of a data section can only
+/// refer to constants and labels defined before.
+pub fn assemble(src: &str) -> Res<Asm> {
+    // First pass: find the value of all labels and constants, and
+    // keep the rest of the statements for the second pass
+    let mut syms = HashMap::new();
+    let mut labels = vec![];
+    let mut stmts = vec![];
+    let mut data: Option<Word> = None;
+    let mut n_instr: Word = 0;
+    for (line, s) in src.lines().enumerate() {
+        let line = line + 1;
+        let err = |msg| (line, msg);
+        let mut s = s.split(';').next().unwrap().trim();
+        while let Some((label, rest)) = s.split_once(':') {
+            let label = label.trim();
+            if !is_ident(label) {
+                break;
+            }
+            let adr = data.unwrap_or(n_instr);
+            define(&mut syms, label, adr).map_err(err)?;
+            labels.push((label.to_string(), adr));
+            s = rest.trim();
+        }
+        if s.is_empty() {
+            continue;
+        }
+        let (op, ops) = match s.split_once(char::is_whitespace) {
+            Some((op, ops)) => (op, operands(ops)),
+            None => (s, vec![]),
+        };
+        match op {
+            ".equ" => {
+                let [name, x] = ops[..] else {
+                    return Err(err("expected a name and a value"));
+                };
+                if !is_ident(name) {
+                    return Err(err("invalid name"));
+                }
+                let x = imm(&syms, x).map_err(err)?;
+                define(&mut syms, name, x).map_err(err)?;
+            }
+            ".data" => {
+                let [adr] = ops[..] else {
+                    return Err(err("expected an address"));
+                };
+                data = Some(imm(&syms, adr).map_err(err)?);
+            }
+            ".text" => data = None,
+            ".word" => {
+                let Some(adr) = data else {
+                    return Err(err("data words outside a data section"));
+                };
+                let n = Word::try_from(ops.len()).unwrap();
+                stmts.push((line, Stmt::Words(adr), ops));
+                data = Some(adr.checked_add(n).ok_or(err("address out of range"))?);
+            }
+            ".region" => stmts.push((line, Stmt::Region, ops)),
+            _ if op.starts_with('.') => return Err(err("unknown directive")),
+            _ if data.is_some() => return Err(err("instruction in a data section")),
+            _ => {
+                stmts.push((line, Stmt::Inst(op, n_instr), ops));
+                n_instr += 1;
+            }
+        }
+    }
+
+    // Second pass: assemble the instructions and data, now that all
+    // labels are known
+    let mut prog = Prog::default();
+    let mut lines = vec![];
+    for (line, stmt, ops) in stmts {
+        let err = |msg| (line, msg);
+        match stmt {
+            Stmt::Inst(op, pc) => {
+                prog.code.push(inst(&syms, op, &ops, pc).map_err(err)?);
+                lines.push(line);
+            }
+            Stmt::Words(adr) => {
+                for (adr, x) in (adr..).zip(ops) {
+                    prog.data.push((adr, imm(&syms, x).map_err(err)?));
+                }
+            }
+            Stmt::Region => {
+                let [start, end, perm] = ops[..] else {
+                    return Err(err("expected start, end and permission"));
+                };
+                let perm = match perm.to_lowercase().as_str() {
+                    "r" => Perm::Read,
+                    "rw" => Perm::ReadWrite,
+                    _ => return Err(err("expected permission r or rw")),
+                };
+                let start = imm(&syms, start).map_err(err)?;
+                let end = imm(&syms, end).map_err(err)?;
+                if end == Word::MAX {
+                    return Err(err("region reaches the highest address"));
+                }
+                prog.regions.push(Region { start, end, perm });
+            }
+        }
+    }
+    Ok(Asm {
+        prog,
+        lines,
+        labels,
+    })
+}
+
+/// Statements of the second pass
+enum Stmt<'a> {
+    /// Mnemonic and index of an instruction
+    Inst(&'a str, Word),
+    /// Data words at the address
+    Words(Word),
+    Region,
+}
+
+/// Returns the instruction with mnemonic op and operands ops at index
+/// pc of the program
+fn inst(syms: &Syms, op: &str, ops: &[&str], pc: Word) -> Result<Inst, &'static str> {
+    use Inst::*;
+    let op = op.to_lowercase();
+    let (op, cc) = match op.split_once('.') {
+        Some((op, cc)) => (op, Some(cond(cc)?)),
+        None => (op.as_str(), None),
+    };
+    if cc.is_some() && !matches!(op, "b" | "brel") {
+        return Err("only branches have a condition");
+    }
+    let n_ops = match op {
+        "mvn" | "cmp" | "tst" | "mov" | "ldr" | "str" | "ldrb" | "strb" | "ldrh" | "strh"
+        | "read" => 2,
+        "b" | "brel" | "bl" | "ret" | "out" | "print" => 1,
+        "and" | "xor" | "orr" | "shr" | "shl" | "rotr" | "add" | "sub" | "mul" | "umulh"
+        | "udiv" | "urem" => 3,
+        "csel" => 4,
+        _ => return Err("unknown mnemonic"),
+    };
+    if ops.len() != n_ops {
+        return Err("wrong number of operands");
+    }
+    let r = |k: usize| reg(ops[k]);
+    let v = |k: usize| val(syms, ops[k]);
+    let i = match op {
+        "and" => And(r(0)?, r(1)?, v(2)?),
+        "xor" => Xor(r(0)?, r(1)?, v(2)?),
+        "orr" => Orr(r(0)?, r(1)?, r(2)?),
+        "mvn" => Mvn(r(0)?, r(1)?),
+        "shr" | "shl" | "rotr" => {
+            let (dst, x) = (r(0)?, r(1)?);
+            match (v(2)?, op) {
+                (Val::Reg(y), "shr") => ShrR(dst, y, x),
+                (Val::Reg(y), "shl") => ShlR(dst, y, x),
+                (Val::Reg(y), _) => RotrR(dst, y, x),
+                (Val::Const(y), _) if y >= Word::from(Word::BITS) => {
+                    return Err("shift amount out of range")
+                }
+                (Val::Const(y), "shr") => Shr(dst, y, x),
+                (Val::Const(y), "shl") => Shl(dst, y, x),
+                (Val::Const(y), _) => Rotr(dst, y, x),
+            }
+        }
+        "add" => Add(r(0)?, r(1)?, v(2)?),
+        "sub" => Sub(r(0)?, r(1)?, v(2)?),
+        "mul" => Mul(r(0)?, r(1)?, r(2)?),
+        "umulh" => Umulh(r(0)?, r(1)?, r(2)?),
+        "udiv" => Udiv(r(0)?, r(1)?, r(2)?),
+        "urem" => Urem(r(0)?, r(1)?, r(2)?),
+        "cmp" => Cmp(r(0)?, v(1)?),
+        "tst" => Tst(r(0)?, r(1)?),
+        "mov" => Mov(r(0)?, v(1)?),
+        "csel" => Csel(r(0)?, r(1)?, r(2)?, cond(&ops[3].to_lowercase())?),
+        "ldr" => Ldr(r(0)?, r(1)?),
+        "str" => Str(r(0)?, r(1)?),
+        "ldrb" => Ldrb(r(0)?, r(1)?),
+        "strb" => Strb(r(0)?, r(1)?),
+        "ldrh" => Ldrh(r(0)?, r(1)?),
+        "strh" => Strh(r(0)?, r(1)?),
+        "b" => B(cc, v(0)?),
+        "brel" => {
+            // A label is the target, and a number the offset
+            let offset = match syms.get(ops[0]) {
+                Some(adr) => i128::from(*adr) - i128::from(pc),
+                None => number(ops[0]).ok_or("expected a label or an offset")?,
+            };
+            let offset = i32::try_from(offset).map_err(|_| "offset out of range")?;
+            BRel(cc, offset)
+        }
+        "bl" => Bl(v(0)?),
+        "ret" => Ret(v(0)?),
+        "read" => {
+            let tape = match ops[1].to_lowercase().as_str() {
+                "public" => Tape::Public,
+                "private" => Tape::Private,
+                _ => return Err("expected tape public or private"),
+            };
+            Read(r(0)?, tape)
+        }
+        "out" => Out(r(0)?),
+        "print" => Print(r(0)?),
+        _ => unreachable!(),
+    };
+    Ok(i)
+}
+
+type Syms = HashMap<String, Word>;
+
+fn define(syms: &mut Syms, name: &str, x: Word) -> Result<(), &'static str> {
+    if reg(name).is_ok() {
+        return Err("register name used as symbol");
+    }
+    match syms.insert(name.to_string(), x) {
+        None => Ok(()),
+        Some(_) => Err("symbol defined twice"),
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut cs = s.chars();
+    cs.next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && cs.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn operands(s: &str) -> Vec<&str> {
+    s.split(',').map(str::trim).collect()
+}
+
+/// Parses a register, i.e r0, ..., r31, pc or lr
+fn reg(s: &str) -> Result<Reg, &'static str> {
+    let s = s.to_lowercase();
+    match s.as_str() {
+        "pc" => Ok(reg::PC),
+        "lr" => Ok(reg::LR),
+        _ => s
+            .strip_prefix('r')
+            .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|n| n.parse::<Reg>().ok())
+            .filter(|r| usize::from(*r) < MAX_N_REG)
+            .ok_or("expected a register"),
+    }
+}
+
+/// Parses a register or an immediate
+fn val(syms: &Syms, s: &str) -> Result<Val, &'static str> {
+    match reg(s) {
+        Ok(r) => Ok(Val::Reg(r)),
+        Err(_) => {
+            let c = imm(syms, s)?;
+            // Constants of instructions are encoded in 32 bits
+            if u32::try_from(u64::from(c)).is_err() {
+                return Err("constant does not fit in 32 bits");
+            }
+            Ok(Val::Const(c))
+        }
+    }
+}
+
+/// Parses an immediate, i.e a number, label or constant. Negative
+/// numbers are represented in two's complement.
+fn imm(syms: &Syms, s: &str) -> Result<Word, &'static str> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    if let Some(x) = syms.get(s) {
+        return Ok(*x);
+    }
+    if is_ident(s) {
+        return Err("undefined symbol");
+    }
+    let x = number(s).ok_or("expected a number")?;
+    let min = -(1 << (Word::BITS - 1));
+    let x = if x < 0 && x >= min {
+        x + (1 << Word::BITS)
+    } else {
+        x
+    };
+    Word::try_from(x).map_err(|_| "number out of range")
+}
+
+/// Parses a (possibly negative) decimal, hexadecimal or binary number
+pub fn number(s: &str) -> Option<i128> {
+    let s = s.replace('_', "");
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(&s)),
+    };
+    let x = if let Some(s) = s.strip_prefix("0x") {
+        i128::from_str_radix(s, 16)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        i128::from_str_radix(s, 2)
+    } else {
+        s.parse()
+    };
+    let x = x.ok()?;
+    Some(if neg { -x } else { x })
+}
+
+/// Parses a condition code, e.g eq
+fn cond(s: &str) -> Result<CondCode, &'static str> {
+    use CondCode::*;
+    let cc = match s {
+        "eq" => Eq,
+        "ne" => Ne,
+        "hs" => Hs,
+        "lo" => Lo,
+        "mi" => Mi,
+        "pl" => Pl,
+        "vs" => Vs,
+        "vc" => Vc,
+        "hi" => Hi,
+        "ls" => Ls,
+        "ge" => Ge,
+        "lt" => Lt,
+        "gt" => Gt,
+        "le" => Le,
+        _ => return Err("unknown condition code"),
+    };
+    Ok(cc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::miniram::builder::Builder;
+    use crate::miniram::encode::encode;
+    use crate::miniram::interpreter::interpret;
+    use crate::miniram::lang::{reg::*, CondCode, Tape, Word};
+
+    #[test]
+    fn mul_eq() {
+        let src = include_str!("../../mram/mul_eq.mram");
+        let p = &assemble(src).unwrap().prog;
+        for args in [vec![2, 2, 4], vec![1, 7, 7], vec![31, 65, 31 * 65]] {
+            let res = interpret(p, args, vec![], vec![], Some(1000));
+            assert_eq!(res.unwrap().0, 0);
+        }
+    }
+
+    #[test]
+    fn all_instructions() {
+        let src = "
+            AND r1, r2, r3
+            AND r1, r2, 0xFF
+            XOR r1, r2, r3
+            XOR r1, r2, #7
+            ORR r1, r2, r3
+            MVN r1, r2
+            SHR r1, r2, 3
+            SHL r1, r2, 3
+            ROTR r1, r2, 3
+            SHR r1, r2, r3
+            SHL r1, r2, r3
+            ROTR r1, r2, r3
+            ADD r1, r2, r3
+            ADD r1, r2, 1
+            SUB r1, r2, r3
+            SUB r1, r2, #0xFFFF_FFFF
+            MUL r1, r2, r3
+            UMULH r1, r2, r3
+            UDIV r1, r2, r3
+            UREM r1, r2, r3
+            CMP r1, r2
+            CMP r1, 0b101
+            TST r1, r2
+            MOV r1, pc
+            MOV r1, 42
+            CSEL r1, r2, r3, lt
+            LDR r1, r2
+            STR r1, r2
+            LDRB r1, r2
+            STRB r1, r2
+            LDRH r1, r2
+            STRH r1, r2
+            B lr
+            B.hi 3
+            BREL -2
+            brel.NE +2
+            BL r4
+            BL 0
+            RET r1
+            RET 0
+            READ r1, public
+            READ r2, private
+            OUT r1
+            PRINT r1
+        ";
+        let p = Builder::new()
+            .and(R1, R2, R3)
+            .and_c(R1, R2, 0xFF)
+            .xor(R1, R2, R3)
+            .xor_c(R1, R2, 7)
+            .or(R1, R2, R3)
+            .not(R1, R2)
+            .shr(R1, 3, R2)
+            .shl(R1, 3, R2)
+            .rotr(R1, 3, R2)
+            .shr_r(R1, R3, R2)
+            .shl_r(R1, R3, R2)
+            .rotr_r(R1, R3, R2)
+            .add(R1, R2, R3)
+            .add_c(R1, R2, 1)
+            .sub(R1, R2, R3)
+            .sub_c(R1, R2, 0xFFFF_FFFF)
+            .mul(R1, R2, R3)
+            .umulh(R1, R2, R3)
+            .udiv(R1, R2, R3)
+            .urem(R1, R2, R3)
+            .cmp(R1, R2)
+            .cmp_c(R1, 5)
+            .tst(R1, R2)
+            .mov_r(R1, PC)
+            .mov_c(R1, 42)
+            .csel(R1, R2, R3, CondCode::Lt)
+            .ldr(R1, R2)
+            .strr(R1, R2)
+            .ldrb(R1, R2)
+            .strb(R1, R2)
+            .ldrh(R1, R2)
+            .strh(R1, R2)
+            .ret_lr()
+            .b_cond_c(CondCode::Hi, 3)
+            .b_rel(-2)
+            .b_cond_rel(CondCode::Ne, 2)
+            .bl(R4)
+            .bl_c(0)
+            .ret_r(R1)
+            .ret_c(0)
+            .read(R1, Tape::Public)
+            .hint(R2)
+            .out(R1)
+            .print(R1)
+            .build();
+        let asm = assemble(src).unwrap();
+        assert_eq!(encode(&asm.prog), encode(&p));
+        assert_eq!(asm.lines, (2..=45).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let src = "
+            .equ N, 3
+            .region tab, end, r
+            .region 0, 16, rw
+
+            .data 100
+            tab: .word 3, 5, -1
+            end:
+            .text
+
+                    MOV r1, tab     ; sum the table
+                    MOV r2, 0
+            loop:   LDR r3, r1
+                    ADD r2, r2, r3
+                    ADD r1, r1, 1
+                    CMP r1, end
+                    BREL.NE loop
+                    SUB r2, r2, 7
+                    MOV r4, N
+                    STR r4, r2
+                    RET r2
+        ";
+        let asm = assemble(src).unwrap();
+        assert_eq!(asm.prog.data, vec![(100, 3), (101, 5), (102, Word::MAX)]);
+        assert_eq!(asm.prog.regions.len(), 2);
+        let labels = [("tab", 100), ("end", 103), ("loop", 2)].map(|(l, x)| (l.to_string(), x));
+        assert_eq!(asm.labels, labels);
+        assert_eq!(asm.lines[2], 13);
+        let res = interpret(&asm.prog, vec![], vec![], vec![], Some(30));
+        assert_eq!(res.unwrap().0, 0);
+    }
+
+    #[test]
+    fn errors() {
+        for (src, line, msg) in [
+            ("MOV r1, 0\nFOO r1", 2, "unknown mnemonic"),
+            ("MOV r1, x", 1, "undefined symbol"),
+            ("MOV r32, 0", 1, "expected a register"),
+            ("ADD r1, r2", 1, "wrong number of operands"),
+            ("a: RET 0\na: RET 1", 2, "symbol defined twice"),
+            ("r1: RET 0", 1, "register name used as symbol"),
+            ("SHR r1, r2, 64", 1, "shift amount out of range"),
+            ("ADD.eq r1, r1, 1", 1, "only branches have a condition"),
+            ("B.al 0", 1, "unknown condition code"),
+            (".data 0\nRET 0", 2, "instruction in a data section"),
+            (".word 1", 1, "data words outside a data section"),
+            (".byte 1", 1, "unknown directive"),
+            ("\n\nREAD r1, secret", 3, "expected tape public or private"),
+            ("MOV r1, 0x1_0000_0000_0000_0000", 1, "number out of range"),
+        ] {
+            assert_eq!(assemble(src).unwrap_err(), (line, msg), "{src}");
+        }
+
+        // The highest address can't hold data or end a region
+        let max = Word::MAX;
+        let src = &format!(".data {max}\n.word 1");
+        assert_eq!(assemble(src).unwrap_err(), (2, "address out of range"));
+        let src = &format!(".region 0, {max}, rw");
+        let err = "region reaches the highest address";
+        assert_eq!(assemble(src).unwrap_err(), (1, err));
+    }
+}
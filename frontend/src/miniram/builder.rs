@@ -1,48 +1,205 @@
 use crate::miniram::lang::*;
 
 pub struct Builder {
-    p: Prog,
+    p: Vec<Inst>,
+    data: Vec<(Word, Word)>,
+    regions: Vec<Region>,
+    /// Labels and their addresses
+    labels: Vec<(String, Word)>,
+    /// Instructions referring to a label, patched in build
+    refs: Vec<(usize, String)>,
+    /// Number of labels generated by the block helpers
+    n_anon: usize,
 }
 
 #[allow(dead_code)]
 impl Builder {
     pub fn new() -> Self {
-        Builder { p: vec![] }
+        Builder {
+            p: vec![],
+            data: vec![],
+            regions: vec![],
+            labels: vec![],
+            refs: vec![],
+            n_anon: 0,
+        }
     }
-    pub fn build(self) -> Prog {
-        self.p
+    /// Returns the program, with the labels it refers to resolved to
+    /// their addresses.
+    ///
+    /// Panics if a label is used but never defined
+    pub fn build(mut self) -> Prog {
+        for (i, name) in &self.refs {
+            let Some((_, adr)) = self.labels.iter().find(|(l, _)| l == name) else {
+                panic!("undefined label {name}")
+            };
+            match &mut self.p[*i] {
+                Inst::B(_, v) | Inst::Bl(v) | Inst::Mov(_, v) => *v = Val::Const(*adr),
+                _ => unreachable!(),
+            }
+        }
+        Prog {
+            code: self.p,
+            data: self.data,
+            regions: self.regions,
+        }
+    }
+    /// Add the words of values at adr, adr + 1, ... to the data image
+    pub fn data(mut self, adr: Word, values: &[Word]) -> Self {
+        for (k, v) in (adr..).zip(values) {
+            self.data.push((k, *v));
+        }
+        self
+    }
+    /// Declare the memory words at start, ..., end - 1 as a region
+    /// of the program with permission perm
+    pub fn region(mut self, start: Word, end: Word, perm: Perm) -> Self {
+        self.regions.push(Region { start, end, perm });
+        self
+    }
+    /// Address of the next instruction pushed to the builder
+    pub fn next_pc(&self) -> Word {
+        Word::try_from(self.p.len()).unwrap()
+    }
+    /// Define the label name at the address of the next instruction.
+    /// Labels starting with '.' are reserved for the block helpers.
+    ///
+    /// Panics if name is already defined
+    pub fn label(mut self, name: &str) -> Self {
+        assert!(
+            self.labels.iter().all(|(l, _)| l != name),
+            "label {name} defined twice"
+        );
+        let pc = self.next_pc();
+        self.labels.push((name.to_string(), pc));
+        self
+    }
+    /// Returns a fresh label for the block helpers
+    fn anon(&mut self) -> String {
+        self.n_anon += 1;
+        format!(".{}", self.n_anon)
+    }
+    /// Push i, whose constant is replaced by the address of label in
+    /// build
+    fn push_ref(mut self, i: Inst, label: &str) -> Self {
+        self.refs.push((self.p.len(), label.to_string()));
+        self.p.push(i);
+        self
+    }
+    /// Loop over the instructions pushed by body while r is not zero,
+    /// i.e
+    ///
+    ///   head: CMP r, 0
+    ///         B.EQ end
+    ///         body
+    ///         B head
+    ///   end:
+    pub fn while_nz(mut self, r: Reg, body: impl FnOnce(Self) -> Self) -> Self {
+        let (head, end) = (self.anon(), self.anon());
+        let b = self.label(&head).cmp_c(r, 0).b_cond_l(CondCode::Eq, &end);
+        body(b).b_l(&head).label(&end)
+    }
+    /// Execute the instructions pushed by body if r is zero, i.e
+    ///
+    ///         CMP r, 0
+    ///         B.NE end
+    ///         body
+    ///   end:
+    pub fn if_z(mut self, r: Reg, body: impl FnOnce(Self) -> Self) -> Self {
+        let end = self.anon();
+        let b = self.cmp_c(r, 0).b_cond_l(CondCode::Ne, &end);
+        body(b).label(&end)
     }
     pub fn add(mut self, z: Reg, x: Reg, y: Reg) -> Self {
-        self.p.push(Inst::Add(z, x, y));
+        self.p.push(Inst::Add(z, x, Val::Reg(y)));
+        self
+    }
+    pub fn add_c(mut self, z: Reg, x: Reg, c: Word) -> Self {
+        self.p.push(Inst::Add(z, x, Val::Const(c)));
         self
     }
     pub fn and(mut self, z: Reg, x: Reg, y: Reg) -> Self {
-        self.p.push(Inst::And(z, x, y));
+        self.p.push(Inst::And(z, x, Val::Reg(y)));
+        self
+    }
+    pub fn and_c(mut self, z: Reg, x: Reg, c: Word) -> Self {
+        self.p.push(Inst::And(z, x, Val::Const(c)));
+        self
+    }
+    pub fn or(mut self, z: Reg, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Orr(z, x, y));
         self
     }
-    pub fn or(mut self, z: Reg, x: Reg, y: Reg, scratch: Reg) -> Self {
-        self.p.push(Inst::Mov(scratch, Val::Const(0xFFFFFFFF)));
-        self.p.push(Inst::Xor(z, x, scratch)); // not x
-        self.p.push(Inst::Xor(scratch, y, scratch)); // not y
-        self.p.push(Inst::And(z, z, scratch));
-        self.p.push(Inst::Mov(scratch, Val::Const(0xFFFFFFFF)));
-        self.p.push(Inst::Xor(z, z, scratch)); // not ((not x) & (not y))
+    pub fn not(mut self, z: Reg, x: Reg) -> Self {
+        self.p.push(Inst::Mvn(z, x));
         self
     }
     pub fn xor(mut self, z: Reg, x: Reg, y: Reg) -> Self {
-        self.p.push(Inst::Xor(z, x, y));
+        self.p.push(Inst::Xor(z, x, Val::Reg(y)));
+        self
+    }
+    pub fn xor_c(mut self, z: Reg, x: Reg, c: Word) -> Self {
+        self.p.push(Inst::Xor(z, x, Val::Const(c)));
         self
     }
     pub fn shr(mut self, dst: Reg, i: Word, x: Reg) -> Self {
         self.p.push(Inst::Shr(dst, i, x));
         self
     }
+    pub fn shl(mut self, dst: Reg, i: Word, x: Reg) -> Self {
+        self.p.push(Inst::Shl(dst, i, x));
+        self
+    }
     pub fn rotr(mut self, dst: Reg, i: Word, x: Reg) -> Self {
         self.p.push(Inst::Rotr(dst, i, x));
         self
     }
+    pub fn shr_r(mut self, dst: Reg, i: Reg, x: Reg) -> Self {
+        self.p.push(Inst::ShrR(dst, i, x));
+        self
+    }
+    pub fn shl_r(mut self, dst: Reg, i: Reg, x: Reg) -> Self {
+        self.p.push(Inst::ShlR(dst, i, x));
+        self
+    }
+    pub fn rotr_r(mut self, dst: Reg, i: Reg, x: Reg) -> Self {
+        self.p.push(Inst::RotrR(dst, i, x));
+        self
+    }
     pub fn sub(mut self, z: Reg, x: Reg, y: Reg) -> Self {
-        self.p.push(Inst::Sub(z, x, y));
+        self.p.push(Inst::Sub(z, x, Val::Reg(y)));
+        self
+    }
+    pub fn sub_c(mut self, z: Reg, x: Reg, c: Word) -> Self {
+        self.p.push(Inst::Sub(z, x, Val::Const(c)));
+        self
+    }
+    pub fn mul(mut self, z: Reg, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Mul(z, x, y));
+        self
+    }
+    pub fn umulh(mut self, z: Reg, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Umulh(z, x, y));
+        self
+    }
+    pub fn udiv(mut self, z: Reg, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Udiv(z, x, y));
+        self
+    }
+    pub fn urem(mut self, z: Reg, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Urem(z, x, y));
+        self
+    }
+    pub fn cmp(mut self, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Cmp(x, Val::Reg(y)));
+        self
+    }
+    pub fn cmp_c(mut self, x: Reg, c: Word) -> Self {
+        self.p.push(Inst::Cmp(x, Val::Const(c)));
+        self
+    }
+    pub fn tst(mut self, x: Reg, y: Reg) -> Self {
+        self.p.push(Inst::Tst(x, y));
         self
     }
     pub fn mov_r(mut self, dst: Reg, src: Reg) -> Self {
@@ -53,12 +210,63 @@ impl Builder {
         self.p.push(Inst::Mov(dst, Val::Const(c)));
         self
     }
+    pub fn csel(mut self, dst: Reg, x: Reg, y: Reg, cc: CondCode) -> Self {
+        self.p.push(Inst::Csel(dst, x, y, cc));
+        self
+    }
     pub fn b_z(mut self, dst: Reg) -> Self {
-        self.p.push(Inst::B(Some(Cond::Z), dst));
+        self.p.push(Inst::B(Some(CondCode::Eq), Val::Reg(dst)));
+        self
+    }
+    pub fn b_cond(mut self, cc: CondCode, dst: Reg) -> Self {
+        self.p.push(Inst::B(Some(cc), Val::Reg(dst)));
+        self
+    }
+    pub fn b_cond_c(mut self, cc: CondCode, adr: Word) -> Self {
+        self.p.push(Inst::B(Some(cc), Val::Const(adr)));
+        self
+    }
+    pub fn b_cond_rel(mut self, cc: CondCode, offset: i32) -> Self {
+        self.p.push(Inst::BRel(Some(cc), offset));
         self
     }
     pub fn b(mut self, dst: Reg) -> Self {
-        self.p.push(Inst::B(None, dst));
+        self.p.push(Inst::B(None, Val::Reg(dst)));
+        self
+    }
+    pub fn b_c(mut self, adr: Word) -> Self {
+        self.p.push(Inst::B(None, Val::Const(adr)));
+        self
+    }
+    pub fn b_rel(mut self, offset: i32) -> Self {
+        self.p.push(Inst::BRel(None, offset));
+        self
+    }
+    pub fn b_l(self, label: &str) -> Self {
+        self.push_ref(Inst::B(None, Val::Const(0)), label)
+    }
+    pub fn b_cond_l(self, cc: CondCode, label: &str) -> Self {
+        self.push_ref(Inst::B(Some(cc), Val::Const(0)), label)
+    }
+    pub fn bl_l(self, label: &str) -> Self {
+        self.push_ref(Inst::Bl(Val::Const(0)), label)
+    }
+    /// Move the address of label to dst
+    pub fn mov_l(self, dst: Reg, label: &str) -> Self {
+        self.push_ref(Inst::Mov(dst, Val::Const(0)), label)
+    }
+    pub fn bl(mut self, dst: Reg) -> Self {
+        self.p.push(Inst::Bl(Val::Reg(dst)));
+        self
+    }
+    pub fn bl_c(mut self, adr: Word) -> Self {
+        self.p.push(Inst::Bl(Val::Const(adr)));
+        self
+    }
+    /// Return from subroutine, i.e branch to the address in LR. Not
+    /// to be confused with ret_r/ ret_c, which halts the machine.
+    pub fn ret_lr(mut self) -> Self {
+        self.p.push(Inst::B(None, Val::Reg(reg::LR)));
         self
     }
     pub fn ldr(mut self, dst: Reg, src: Reg) -> Self {
@@ -69,6 +277,22 @@ impl Builder {
         self.p.push(Inst::Str(dst, src));
         self
     }
+    pub fn ldrb(mut self, dst: Reg, src: Reg) -> Self {
+        self.p.push(Inst::Ldrb(dst, src));
+        self
+    }
+    pub fn strb(mut self, dst: Reg, src: Reg) -> Self {
+        self.p.push(Inst::Strb(dst, src));
+        self
+    }
+    pub fn ldrh(mut self, dst: Reg, src: Reg) -> Self {
+        self.p.push(Inst::Ldrh(dst, src));
+        self
+    }
+    pub fn strh(mut self, dst: Reg, src: Reg) -> Self {
+        self.p.push(Inst::Strh(dst, src));
+        self
+    }
 
     pub fn ret_r(mut self, r: Reg) -> Self {
         self.p.push(Inst::Ret(Val::Reg(r)));
@@ -80,8 +304,83 @@ impl Builder {
         self
     }
 
+    pub fn read(mut self, r: Reg, tape: Tape) -> Self {
+        self.p.push(Inst::Read(r, tape));
+        self
+    }
+    /// Read a hint from the private tape
+    pub fn hint(mut self, r: Reg) -> Self {
+        self.p.push(Inst::Read(r, Tape::Private));
+        self
+    }
+    /// Append the value of r to the public outputs
+    pub fn out(mut self, r: Reg) -> Self {
+        self.p.push(Inst::Out(r));
+        self
+    }
     pub fn print(mut self, r: Reg) -> Self {
         self.p.push(Inst::Print(r));
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Builder;
+    use crate::miniram::lang::{CondCode, Inst, Val};
+
+    #[test]
+    fn labels() {
+        let p = Builder::new()
+            .b_l("end")
+            .label("top")
+            .mov_l(1, "end")
+            .b_cond_l(CondCode::Ne, "top")
+            .label("end")
+            .bl_l("top")
+            .build();
+        assert_eq!(p.code[0], Inst::B(None, Val::Const(3)));
+        assert_eq!(p.code[1], Inst::Mov(1, Val::Const(3)));
+        assert_eq!(p.code[2], Inst::B(Some(CondCode::Ne), Val::Const(1)));
+        assert_eq!(p.code[3], Inst::Bl(Val::Const(1)));
+    }
+
+    #[test]
+    fn blocks_zero_on_entry() {
+        use crate::miniram::interpreter::interpret;
+        // The loop is skipped if r1 is zero on entry, and the block
+        // of if_z is run if r1 is zero after the loop
+        let p = |x| {
+            Builder::new()
+                .mov_c(1, x)
+                .mov_c(2, 7)
+                .while_nz(1, |b| b.mov_c(2, 1).mov_c(1, 0))
+                .if_z(1, |b| b.add_c(2, 2, 1))
+                .ret_r(2)
+                .build()
+        };
+        for (x, res) in [(0, 8), (3, 2)] {
+            let res_ = interpret(&p(x), vec![], vec![], vec![], Some(20));
+            assert_eq!(res_.unwrap().0, res, "r1 = {x}");
+        }
+
+        let p = &Builder::new()
+            .mov_c(1, 5)
+            .if_z(1, |b| b.ret_c(1))
+            .ret_c(0)
+            .build();
+        assert_eq!(interpret(p, vec![], vec![], vec![], Some(10)).unwrap().0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "undefined label end")]
+    fn undefined_label() {
+        Builder::new().b_l("end").build();
+    }
+
+    #[test]
+    #[should_panic(expected = "label top defined twice")]
+    fn label_defined_twice() {
+        let _ = Builder::new().label("top").ret_c(0).label("top");
+    }
+}
@@ -1,4 +1,4 @@
-use self::reg::{PC, R1};
+use self::reg::{LR, PC, R1};
 
 use super::lang::Inst::*;
 use super::lang::*;
@@ -6,19 +6,23 @@ use super::lang::*;
 /// Type of an encoded instruction.
 ///
 /// Intructions are encoded into a 64 bit word consisting of
-/// 4 fields:
+/// 5 fields:
 ///
-///   #1 (8 bits): opcode          - only uses 6 bits
-///   #2 (8 bits): dst  (register) - only uses 4 bits
-///   #3 (8 bits): arg0 (register) - only uses 4 bits
-///   #4 (8 bits): unused
+///   #1 (8 bits): opcode
+///   #2 (8 bits): dst  (register) - only uses 5 bits, see MAX_N_REG
+///   #3 (8 bits): arg0 (register) - only uses 5 bits
+///   #4 (8 bits): shift amount, condition code or tape - only uses 5 bits
 ///   #5 (32 bits): arg1 (register or word)
+///
+/// Opcodes use all 8 bits, e.g the forms of ADD, SUB, AND, XOR and
+/// CMP with a constant arg1 are the opcodes of the register forms
+/// with the two most significant bits set (see encode_alu_val).
 type EInst64 = u64;
 
 pub type EProg = Vec<EInst64>;
 
 pub fn encode(p: &Prog) -> EProg {
-    p.iter().map(encode_instr).collect()
+    p.code.iter().map(encode_instr).collect()
 }
 
 fn encode_instr(i: &Inst) -> EInst64 {
@@ -27,16 +31,30 @@ fn encode_instr(i: &Inst) -> EInst64 {
             let opcode = 0;
             let dst = encode_reg(x);
             let arg0 = encode_reg(y);
-            let arg1 = u32::from(encode_reg(z));
-            encode_instr_u64(opcode, dst, arg0, arg1)
+            let (arg1, op_offset) = encode_alu_val(z);
+            encode_instr_u64(opcode | op_offset, dst, arg0, arg1)
         }
         Xor(x, y, z) => {
             let opcode = 0b11100;
             let dst = encode_reg(x);
             let arg0 = encode_reg(y);
+            let (arg1, op_offset) = encode_alu_val(z);
+            encode_instr_u64(opcode | op_offset, dst, arg0, arg1)
+        }
+        Orr(x, y, z) => {
+            let opcode = 0b1011100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
             let arg1 = u32::from(encode_reg(z));
             encode_instr_u64(opcode, dst, arg0, arg1)
         }
+        Mvn(x, y) => {
+            let opcode = 0b10000000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
+            let arg1 = 0;
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
         Shr(x, y, z) => {
             let opcode = 0b1000000;
             let dst = encode_reg(x);
@@ -45,6 +63,14 @@ fn encode_instr(i: &Inst) -> EInst64 {
             let arg1 = 0;
             encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
         }
+        Shl(x, y, z) => {
+            let opcode = 0b10000100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(z);
+            let field4 = u8::try_from(*y).unwrap();
+            let arg1 = 0;
+            encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
+        }
         Rotr(x, y, z) => {
             let opcode = 0b1000100;
             let dst = encode_reg(x);
@@ -53,20 +79,85 @@ fn encode_instr(i: &Inst) -> EInst64 {
             let arg1 = 0;
             encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
         }
+        ShrR(x, y, z) => {
+            let opcode = 0b10001000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(z);
+            let arg1 = u32::from(encode_reg(y));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
+        ShlR(x, y, z) => {
+            let opcode = 0b10001100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(z);
+            let arg1 = u32::from(encode_reg(y));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
+        RotrR(x, y, z) => {
+            let opcode = 0b10010000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(z);
+            let arg1 = u32::from(encode_reg(y));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
         Add(x, y, z) => {
             let opcode = 0b100;
             let dst = encode_reg(x);
             let arg0 = encode_reg(y);
-            let arg1 = u32::from(encode_reg(z));
-            encode_instr_u64(opcode, dst, arg0, arg1)
+            let (arg1, op_offset) = encode_alu_val(z);
+            encode_instr_u64(opcode | op_offset, dst, arg0, arg1)
         }
         Sub(x, y, z) => {
             let opcode = 0b1000;
             let dst = encode_reg(x);
             let arg0 = encode_reg(y);
+            let (arg1, op_offset) = encode_alu_val(z);
+            encode_instr_u64(opcode | op_offset, dst, arg0, arg1)
+        }
+        Mul(x, y, z) => {
+            let opcode = 0b1001100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
             let arg1 = u32::from(encode_reg(z));
             encode_instr_u64(opcode, dst, arg0, arg1)
         }
+        Umulh(x, y, z) => {
+            let opcode = 0b1010000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
+            let arg1 = u32::from(encode_reg(z));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
+        Udiv(x, y, z) => {
+            let opcode = 0b101000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
+            let arg1 = u32::from(encode_reg(z));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
+        Urem(x, y, z) => {
+            let opcode = 0b101100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
+            let arg1 = u32::from(encode_reg(z));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
+        Cmp(x, y) => {
+            let opcode = 0b1010100;
+            // NOTICE: CMP/ TST doesn't write any register, so we use
+            // pc as the destination, which is incremented as usual.
+            let dst = PC;
+            let arg0 = encode_reg(x);
+            let (arg1, op_offset) = encode_alu_val(y);
+            encode_instr_u64(opcode | op_offset, dst, arg0, arg1)
+        }
+        Tst(x, y) => {
+            let opcode = 0b1011000;
+            let dst = PC;
+            let arg0 = encode_reg(x);
+            let arg1 = u32::from(encode_reg(y));
+            encode_instr_u64(opcode, dst, arg0, arg1)
+        }
         Mov(x, y) => {
             let opcode = 0b1100;
             let dst = encode_reg(x);
@@ -93,16 +184,60 @@ fn encode_instr(i: &Inst) -> EInst64 {
 
             encode_instr_u64(opcode, arg0, dst, arg1)
         }
+        // The byte and halfword variants are encoded as LDR/ STR with
+        // bit 2 (byte) or bit 3 (halfword) of the opcode set
+        Ldrb(dst, src) => {
+            let opcode = 0b111;
+            let dst = encode_reg(dst);
+            let arg0 = encode_reg(src);
+            encode_instr_u64(opcode, dst, arg0, 0)
+        }
+        Strb(dst, src) => {
+            let opcode = 0b110;
+            let dst = encode_reg(dst);
+            let arg0 = encode_reg(src);
+            encode_instr_u64(opcode, arg0, dst, 0)
+        }
+        Ldrh(dst, src) => {
+            let opcode = 0b1011;
+            let dst = encode_reg(dst);
+            let arg0 = encode_reg(src);
+            encode_instr_u64(opcode, dst, arg0, 0)
+        }
+        Strh(dst, src) => {
+            let opcode = 0b1010;
+            let dst = encode_reg(dst);
+            let arg0 = encode_reg(src);
+            encode_instr_u64(opcode, arg0, dst, 0)
+        }
         B(x, y) => {
-            let opcode = match x {
-                None => 0b10100,
-                Some(Cond::Z) => 0b11000,
+            // Branches to constant addresses are always encoded as
+            // conditional branches, using COND_AL if x is None
+            let (opcode, field4, arg1) = match (x, y) {
+                (None, Val::Reg(r)) => (0b10100, 0, u32::from(encode_reg(r))),
+                (Some(cc), Val::Reg(r)) => (0b11000, *cc as u8, u32::from(encode_reg(r))),
+                (_, Val::Const(c)) => (0b11011000, encode_cond(x), encode_const(c)),
             };
             let dst = PC;
             let arg0 = 0;
-            // todo: use arg0 instead, so arg1 can hold an
-            //       offset
-            let arg1 = u32::from(encode_reg(y));
+            encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
+        }
+        BRel(x, y) => {
+            let opcode = 0b11001100;
+            let dst = PC;
+            let arg0 = 0;
+            let field4 = encode_cond(x);
+            let arg1 = *y as u32;
+            encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
+        }
+        Bl(x) => {
+            let opcode = match x {
+                Val::Reg(_) => 0b10010100,
+                Val::Const(_) => 0b11010000,
+            };
+            let dst = LR;
+            let arg0 = 0;
+            let (arg1, _) = encode_alu_val(x);
             encode_instr_u64(opcode, dst, arg0, arg1)
         }
         Ret(x) => {
@@ -112,6 +247,26 @@ fn encode_instr(i: &Inst) -> EInst64 {
             let (arg1, op_offset) = encode_val(x);
             encode_instr_u64(opcode + op_offset, dst, arg0, arg1)
         }
+        Csel(x, y, z, cc) => {
+            let opcode = 0b10011100;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(y);
+            let field4 = *cc as u8;
+            let arg1 = u32::from(encode_reg(z));
+            encode_extended_instr_u64(opcode, dst, arg0, field4, arg1)
+        }
+        Read(x, y) => {
+            let opcode = 0b10011000;
+            let dst = encode_reg(x);
+            let field4 = *y as u8;
+            encode_extended_instr_u64(opcode, dst, 0, field4, 0)
+        }
+        Out(x) => {
+            let opcode = 0b110000;
+            let dst = encode_reg(x);
+            let arg0 = encode_reg(x);
+            encode_instr_u64(opcode, dst, arg0, 0)
+        }
         Print(_) => {
             let opcode = 0b1001000;
             encode_instr_u64(opcode, 0, 0, 0)
@@ -119,6 +274,133 @@ fn encode_instr(i: &Inst) -> EInst64 {
     }
 }
 
+/// Decodes each instruction of p (see decode_instr). Returns the
+/// program (without data image and regions), or the index of the
+/// first malformed instruction and why it is malformed.
+pub fn decode(p: &[EInst64]) -> Result<Prog, (usize, &'static str)> {
+    let code = p
+        .iter()
+        .enumerate()
+        .map(|(pc, i)| decode_instr(*i).map_err(|e| (pc, e)))
+        .collect::<Result<_, _>>()?;
+    Ok(Prog {
+        code,
+        ..Prog::default()
+    })
+}
+
+/// Returns the instruction encoded as i, or an error if i is not the
+/// encoding of any instruction, i.e if the opcode is unknown, a
+/// field is out of range or a field unused by the instruction isn't
+/// zero.
+///
+/// The register of PRINT isn't encoded, so it is decoded as PC.
+pub fn decode_instr(i: EInst64) -> Result<Inst, &'static str> {
+    let opcode = (i >> 56) as u8;
+    let dst = decode_reg((i >> 48) as u8)?;
+    let arg0 = decode_reg((i >> 40) as u8)?;
+    let field4 = (i >> 32) as u8;
+    let arg1 = i as u32;
+    let reg1 = || {
+        let r = u8::try_from(arg1).map_err(|_| "register out of range")?;
+        decode_reg(r)
+    };
+    let alu_val = |c| match opcode & 0b11000000 {
+        0b11000000 => Ok(Val::Const(c)),
+        _ => Ok(Val::Reg(reg1()?)),
+    };
+    let shamt = || match Word::from(field4) {
+        x if x < Word::from(Word::BITS) => Ok(x),
+        _ => Err("shift amount out of range"),
+    };
+    let c = Word::from(arg1);
+    let i_ = match opcode {
+        0b0 | 0b11000000 => And(dst, arg0, alu_val(c)?),
+        0b11100 | 0b11011100 => Xor(dst, arg0, alu_val(c)?),
+        0b1011100 => Orr(dst, arg0, reg1()?),
+        0b10000000 => Mvn(dst, arg0),
+        0b1000000 => Shr(dst, shamt()?, arg0),
+        0b10000100 => Shl(dst, shamt()?, arg0),
+        0b1000100 => Rotr(dst, shamt()?, arg0),
+        0b10001000 => ShrR(dst, reg1()?, arg0),
+        0b10001100 => ShlR(dst, reg1()?, arg0),
+        0b10010000 => RotrR(dst, reg1()?, arg0),
+        0b100 | 0b11000100 => Add(dst, arg0, alu_val(c)?),
+        0b1000 | 0b11001000 => Sub(dst, arg0, alu_val(c)?),
+        0b1001100 => Mul(dst, arg0, reg1()?),
+        0b1010000 => Umulh(dst, arg0, reg1()?),
+        0b101000 => Udiv(dst, arg0, reg1()?),
+        0b101100 => Urem(dst, arg0, reg1()?),
+        0b1010100 | 0b11010100 => Cmp(arg0, alu_val(c)?),
+        0b1011000 => Tst(arg0, reg1()?),
+        0b1100 => Mov(dst, Val::Reg(reg1()?)),
+        0b10000 => Mov(dst, Val::Const(c)),
+        0b10011100 => Csel(dst, arg0, reg1()?, decode_cond(field4)?),
+        0b11 => Ldr(dst, arg0),
+        0b10 => Str(arg0, dst),
+        0b111 => Ldrb(dst, arg0),
+        0b110 => Strb(arg0, dst),
+        0b1011 => Ldrh(dst, arg0),
+        0b1010 => Strh(arg0, dst),
+        0b10100 => B(None, Val::Reg(reg1()?)),
+        0b11000 => B(Some(decode_cond(field4)?), Val::Reg(reg1()?)),
+        0b11011000 => B(decode_cond_al(field4)?, Val::Const(c)),
+        0b11001100 => BRel(decode_cond_al(field4)?, arg1 as i32),
+        0b10010100 => Bl(Val::Reg(reg1()?)),
+        0b11010000 => Bl(Val::Const(c)),
+        0b100000 => Ret(Val::Reg(reg1()?)),
+        0b100100 => Ret(Val::Const(c)),
+        0b10011000 => match field4 {
+            0 => Read(dst, Tape::Private),
+            1 => Read(dst, Tape::Public),
+            _ => return Err("invalid tape"),
+        },
+        0b110000 => Out(dst),
+        0b1001000 => Print(PC),
+        _ => return Err("unknown opcode"),
+    };
+    // The remaining fields must be as encoded, e.g zero if unused
+    if encode_instr(&i_) != i {
+        return Err("malformed instruction");
+    }
+    Ok(i_)
+}
+
+fn decode_reg(r: u8) -> Result<Reg, &'static str> {
+    match usize::from(r) < MAX_N_REG {
+        true => Ok(r),
+        false => Err("register out of range"),
+    }
+}
+
+fn decode_cond(cc: u8) -> Result<CondCode, &'static str> {
+    use strum::IntoEnumIterator;
+    CondCode::iter()
+        .find(|c| *c as u8 == cc)
+        .ok_or("invalid condition code")
+}
+
+/// Decodes the condition of a branch, where COND_AL is no condition
+fn decode_cond_al(cc: u8) -> Result<Option<CondCode>, &'static str> {
+    match cc {
+        COND_AL => Ok(None),
+        _ => decode_cond(cc).map(Some),
+    }
+}
+
+/// Returns a listing of the encoded program p in the syntax of the
+/// assembler (see asm::assemble), with the index and the encoding of
+/// each instruction as comment. The encoding is given as the high and
+/// low word, like in the constants of the circuit and in memory.
+pub fn disassemble(p: &[EInst64]) -> Result<String, (usize, &'static str)> {
+    let prog = decode(p)?;
+    let lines = prog.code.iter().zip(p).enumerate().map(|(pc, (i, e))| {
+        let (hi, lo) = (e >> 32, e & 0xFFFF_FFFF);
+        format!("{:<28}; {pc:>4}: {hi:#010x} {lo:#010x}\n", i.to_string())
+    });
+    Ok(lines.collect())
+}
+
 fn encode_instr_u64(opcode: u8, dst: u8, arg0: u8, arg1: u32) -> u64 {
     encode_extended_instr_u64(opcode, dst, arg0, 0, arg1)
 }
@@ -137,6 +419,22 @@ fn encode_reg(reg: &Reg) -> u8 {
     *reg
 }
 
+/// Condition code that always holds
+const COND_AL: u8 = 0b1110;
+
+fn encode_cond(cc: &Option<CondCode>) -> u8 {
+    match cc {
+        None => COND_AL,
+        Some(cc) => *cc as u8,
+    }
+}
+
+/// Constants are stored in the 32 bit arg1 field, also when words
+/// are 64 bits
+fn encode_const(c: &Word) -> u32 {
+    u32::try_from(u64::from(*c)).expect("constant does not fit in 32 bits")
+}
+
 /// Returns encoded value and opcode offset
 fn encode_val(v: &Val) -> (u32, u8) {
     match v {
@@ -144,19 +442,37 @@ fn encode_val(v: &Val) -> (u32, u8) {
             let r = encode_reg(r);
             (u32::from(r), 0)
         }
-        Val::Const(c) => (*c, 0b100),
+        Val::Const(c) => (encode_const(c), 0b100),
+    }
+}
+
+/// Returns encoded value and opcode offset of the second operand of
+/// ALU instructions. The offset sets the two most significant bits
+/// of the opcode, which gives opcodes not used by other instructions
+/// (see decode_instr).
+fn encode_alu_val(v: &Val) -> (u32, u8) {
+    match v {
+        Val::Reg(r) => {
+            let r = encode_reg(r);
+            (u32::from(r), 0)
+        }
+        Val::Const(c) => (encode_const(c), 0b11000000),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::encode_instr;
+    use strum::IntoEnumIterator;
+
+    use super::{decode_instr, disassemble, encode, encode_instr};
+    use crate::miniram::asm::assemble;
     use crate::miniram::lang::reg::*;
     use crate::miniram::lang::Inst::*;
+    use crate::miniram::lang::{CondCode, Inst, Prog, Tape, Val};
 
     #[test]
     fn test_encode() {
-        let i = Add(R1, R1, R1);
+        let i = Add(R1, R1, Val::Reg(R1));
         let enc = encode_instr(&i);
         //          op      dst     arg0    blank                             arg0
         assert_eq!(
@@ -164,7 +480,7 @@ mod tests {
             0b0000_0100_0000_0001_0000_0001_0000_0000_0000_0000_0000_0000_0000_0000_0000_0001
         );
 
-        let i = Add(R2, R1, R1);
+        let i = Add(R2, R1, Val::Reg(R1));
         let enc = encode_instr(&i);
         //          op      dst     arg0    blank                             arg0
         assert_eq!(
@@ -172,7 +488,7 @@ mod tests {
             0b0000_0100_0000_0010_0000_0001_0000_0000_0000_0000_0000_0000_0000_0000_0000_0001
         );
 
-        let i = Sub(R2, R2, R2);
+        let i = Sub(R2, R2, Val::Reg(R2));
         let enc = encode_instr(&i);
         //          op      dst     arg0    blank                             arg0
         assert_eq!(
@@ -180,4 +496,120 @@ mod tests {
             0b0000_1000_0000_0010_0000_0010_0000_0000_0000_0000_0000_0000_0000_0000_0000_0010
         );
     }
+
+    /// Instructions with every opcode
+    fn all_opcodes() -> Vec<Inst> {
+        let (r, c) = (Val::Reg(R3), Val::Const(0xFFFF_FFFF));
+        let mut is = vec![];
+        for v in [r, c] {
+            is.append(&mut vec![
+                And(R1, R2, v),
+                Xor(R1, R2, v),
+                Add(R1, R2, v),
+                Sub(R1, R2, v),
+                Cmp(31, v),
+                Mov(R1, v),
+                B(None, v),
+                B(Some(CondCode::Lt), v),
+                Bl(v),
+                Ret(v),
+            ]);
+        }
+        is.append(&mut vec![
+            Orr(R1, R2, R3),
+            Mvn(R1, R2),
+            Shr(R1, 31, R2),
+            Shl(R1, 1, R2),
+            Rotr(R1, 7, R2),
+            ShrR(R1, R3, R2),
+            ShlR(R1, R3, R2),
+            RotrR(R1, R3, R2),
+            Mul(R1, R2, R3),
+            Umulh(R1, R2, R3),
+            Udiv(R1, R2, R3),
+            Urem(R1, R2, R3),
+            Tst(R1, R2),
+            Ldr(R1, R2),
+            Str(R1, R2),
+            Ldrb(R1, R2),
+            Strb(R1, R2),
+            Ldrh(R1, R2),
+            Strh(R1, R2),
+            BRel(None, -3),
+            BRel(Some(CondCode::Ne), i32::MAX),
+            Read(R1, Tape::Public),
+            Read(R1, Tape::Private),
+            Out(R1),
+            Print(PC),
+        ]);
+        for cc in CondCode::iter() {
+            is.push(Csel(R1, R2, R3, cc));
+        }
+        is
+    }
+
+    #[test]
+    fn test_decode() {
+        for i in all_opcodes() {
+            let enc = encode_instr(&i);
+            assert_eq!(decode_instr(enc), Ok(i));
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let p = Prog {
+            code: all_opcodes(),
+            ..Prog::default()
+        };
+        let listing = disassemble(&encode(&p)).unwrap();
+        let asm = assemble(&listing).unwrap();
+        assert_eq!(asm.prog.code, p.code);
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        let mov = encode_instr(&Mov(R1, Val::Reg(R2)));
+        let shr = encode_instr(&Shr(R1, 1, R2));
+        let csel = encode_instr(&Csel(R1, R2, R3, CondCode::Eq));
+        let read = encode_instr(&Read(R1, Tape::Private));
+        let cmp = encode_instr(&Cmp(R1, Val::Reg(R2)));
+        let out = encode_instr(&Out(R1));
+        for (enc, err) in [
+            (0b110100 << 56, "unknown opcode"),
+            (mov | 32 << 48, "register out of range"),
+            (mov | 1 << 8, "register out of range"),
+            (mov | 1 << 40, "malformed instruction"),
+            (mov | 1 << 32, "malformed instruction"),
+            (shr | 0x40 << 32, "shift amount out of range"),
+            (csel | 14 << 32, "invalid condition code"),
+            (read | 2 << 32, "invalid tape"),
+            (cmp | 1 << 48, "malformed instruction"),
+            (out ^ 3 << 40, "malformed instruction"),
+        ] {
+            assert_eq!(decode_instr(enc), Err(err), "{enc:#018x}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "word64")]
+    fn test_decode_word64() {
+        // Shift amounts up to 63 fit in field #4
+        for i in [Shr(R1, 63, R2), Shl(R1, 32, R2), Rotr(R1, 40, R2)] {
+            let enc = encode_instr(&i);
+            assert_eq!(decode_instr(enc), Ok(i));
+        }
+        let shl = encode_instr(&Shl(R1, 0, R2));
+        assert_eq!(
+            decode_instr(shl | 64 << 32),
+            Err("shift amount out of range")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "word64")]
+    #[should_panic(expected = "constant does not fit in 32 bits")]
+    fn test_encode_word64_const() {
+        encode_instr(&Mov(R1, Val::Const(1 << 32)));
+    }
 }
@@ -2,18 +2,21 @@ use std::cmp::Eq;
 use std::cmp::Ordering;
 use std::collections::HashMap;
 
+use crate::miniram::encode::encode;
 use crate::miniram::lang::reg::*;
 use crate::miniram::lang::*;
 
 type Mem = HashMap<Word, Word>;
-type Store = [Word; N_REG];
+type Store = [Word; MAX_N_REG];
 type Cflags = [bool; N_CFL];
 
 pub type Res<T> = Result<T, &'static str>;
 /// Local state of program execution. Consists of:
 /// - Value of all registers
 /// - Value of conditional flags
-pub type LocalState = (Store, Cflags);
+/// - Number of values read from the public tape
+/// - Number of values output
+pub type LocalState = (Store, Cflags, Word, Word);
 
 /// Local state augmented with information on whether the current
 /// instruction needs memory access
@@ -21,6 +24,9 @@ pub type LocalState = (Store, Cflags);
 pub struct LocalStateAug {
     pub st: LocalState,
     pub ma: MemAccess,
+    /// Word taken from the prover by the instruction, i.e the
+    /// remainder of UDIV and the quotient of UREM (or 0)
+    pub aux: Word,
     pub step: u64,
 }
 
@@ -96,25 +102,74 @@ impl PartialOrd for LocalStateAug {
     }
 }
 
+/// Memory access of an instruction. The address is the address of
+/// the word accessed, also for byte and halfword accesses, and the
+/// values are the values of the whole word.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum MemAccess {
     None,
     Read { addr: Word, val: Word },
-    Write { addr: Word, val: Word },
+    Write { addr: Word, old: Word, val: Word },
 }
 
 /// Executes prog on args for maximum t steps (or until interpreting
-/// a RET instruction, if t is None).
+/// a RET instruction, if t is None). The values of the public and
+/// private tape are read in order by READ instructions. Memory is
+/// initialized with args at address 0, 1, ... and the data image of
+/// prog.
 ///
-/// Returns the result of evaluation, with all local states
-/// encountered during evaluation, or an error if the time bound t
-/// was exceeded.
+/// Returns the result of evaluation, with the values output by OUT
+/// instructions and all local states encountered during evaluation,
+/// or an error if the time bound t was exceeded, the data image
+/// overlaps the arguments, or the data image or regions reach the
+/// highest address.
 pub fn interpret(
     prog: &Prog,
     args: Vec<Word>,
+    public: Vec<Word>,
+    private: Vec<Word>,
     t: Option<usize>,
-) -> Res<(Word, Vec<LocalStateAug>)> {
-    let mut mem = init_mem(args);
+) -> Res<(Word, Vec<Word>, Vec<LocalStateAug>)> {
+    run(prog, args, public, private, t, false)
+}
+
+/// Executes prog like interpret, as in the Von Neumann and Private
+/// mode of the reduction, where instructions are fetched from the
+/// program in memory at ADR_CODE. Memory is also initialized with
+/// the encoded program at ADR_CODE, and storing to addresses from
+/// ADR_CODE is an error, as the circuit only accepts executions of
+/// the program as loaded.
+pub fn interpret_von_neumann(
+    prog: &Prog,
+    args: Vec<Word>,
+    public: Vec<Word>,
+    private: Vec<Word>,
+    t: Option<usize>,
+) -> Res<(Word, Vec<Word>, Vec<LocalStateAug>)> {
+    run(prog, args, public, private, t, true)
+}
+
+/// Executes prog, see interpret. The encoded program is loaded at
+/// ADR_CODE, and stores to addresses from ADR_CODE are errors, if
+/// code is set.
+fn run(
+    prog: &Prog,
+    args: Vec<Word>,
+    public: Vec<Word>,
+    private: Vec<Word>,
+    t: Option<usize>,
+    code: bool,
+) -> Res<(Word, Vec<Word>, Vec<LocalStateAug>)> {
+    let mut mem = init_mem(prog, args, code)?;
+    let check_store = |addr| match code && addr >= ADR_CODE {
+        true => Err("storing to the program code"),
+        false => check_access(prog, addr, true),
+    };
+    let mut public = public.into_iter();
+    let mut private = private.into_iter();
+    // Number of values read from the public tape, and output
+    let mut io: (Word, Word) = (0, 0);
+    let mut outputs = vec![];
     let mut st = init_store();
     let mut cfl = init_cflags();
     let mut sts = vec![];
@@ -123,21 +178,39 @@ pub fn interpret(
     let mut i = fetch(prog, st[pc])?;
     let res = loop {
         // dbg!(&st, &cfl, i);
+        let mut aux = 0;
         let ma = match *i {
             Inst::And(dst, x, y) => {
                 let dst = usize::from(dst);
                 let x = usize::from(x);
-                let y = usize::from(y);
-                let v = st[x] & st[y];
+                let y = val(&st, y);
+                let v = st[x] & y;
                 st[dst] = v;
                 set_flags(&mut cfl, v);
                 MemAccess::None
             }
             Inst::Xor(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = val(&st, y);
+                let v = st[x] ^ y;
+                st[dst] = v;
+                set_flags(&mut cfl, v);
+                MemAccess::None
+            }
+            Inst::Orr(dst, x, y) => {
                 let dst = usize::from(dst);
                 let x = usize::from(x);
                 let y = usize::from(y);
-                let v = st[x] ^ st[y];
+                let v = st[x] | st[y];
+                st[dst] = v;
+                set_flags(&mut cfl, v);
+                MemAccess::None
+            }
+            Inst::Mvn(dst, x) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let v = !st[x];
                 st[dst] = v;
                 set_flags(&mut cfl, v);
                 MemAccess::None
@@ -150,37 +223,131 @@ pub fn interpret(
                 set_flags(&mut cfl, v);
                 MemAccess::None
             }
+            Inst::Shl(dst, x, y) => {
+                let dst = usize::from(dst);
+                let y = usize::from(y);
+                let v = st[y] << x;
+                st[dst] = v;
+                set_flags(&mut cfl, v);
+                MemAccess::None
+            }
             Inst::Rotr(dst, x, y) => {
                 let dst = usize::from(dst);
                 let y = usize::from(y);
-                let v = st[y].rotate_right(x);
+                let v = st[y].rotate_right(shamt(x));
                 st[dst] = v;
                 set_flags(&mut cfl, v);
                 MemAccess::None
             }
-            Inst::Add(dst, x, y) => {
+            Inst::ShrR(dst, x, y) => {
                 let dst = usize::from(dst);
                 let x = usize::from(x);
                 let y = usize::from(y);
-                let v = st[x].wrapping_add(st[y]);
+                let v = st[y] >> shamt(st[x]);
+                st[dst] = v;
                 set_flags(&mut cfl, v);
+                MemAccess::None
+            }
+            Inst::ShlR(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = usize::from(y);
+                let v = st[y] << shamt(st[x]);
+                st[dst] = v;
+                set_flags(&mut cfl, v);
+                MemAccess::None
+            }
+            Inst::RotrR(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = usize::from(y);
+                let v = st[y].rotate_right(shamt(st[x]));
+                st[dst] = v;
+                set_flags(&mut cfl, v);
+                MemAccess::None
+            }
+            Inst::Add(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = val(&st, y);
+                let (v, c) = st[x].overflowing_add(y);
+                let (_, o) = (st[x] as SWord).overflowing_add(y as SWord);
+                set_flags_cv(&mut cfl, v, c, o);
                 st[dst] = v;
                 MemAccess::None
             }
             Inst::Sub(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = val(&st, y);
+                // C is set when the subtraction doesn't borrow
+                let (v, borrow) = st[x].overflowing_sub(y);
+                let (_, o) = (st[x] as SWord).overflowing_sub(y as SWord);
+                set_flags_cv(&mut cfl, v, !borrow, o);
+                st[dst] = v;
+                MemAccess::None
+            }
+            Inst::Mul(dst, x, y) => {
                 let dst = usize::from(dst);
                 let x = usize::from(x);
                 let y = usize::from(y);
-                let v = st[x].wrapping_sub(st[y]);
+                let v = st[x].wrapping_mul(st[y]);
                 set_flags(&mut cfl, v);
                 st[dst] = v;
                 MemAccess::None
             }
+            Inst::Umulh(dst, x, y) => {
+                let dst = usize::from(dst);
+                let x = usize::from(x);
+                let y = usize::from(y);
+                let v = ((DWord::from(st[x]) * DWord::from(st[y])) >> Word::BITS) as Word;
+                set_flags(&mut cfl, v);
+                st[dst] = v;
+                MemAccess::None
+            }
+            Inst::Udiv(dst, x, y) => {
+                let dst = usize::from(dst);
+                let (q, r) = div_rem(st[usize::from(x)], st[usize::from(y)]);
+                set_flags(&mut cfl, q);
+                st[dst] = q;
+                aux = r;
+                MemAccess::None
+            }
+            Inst::Urem(dst, x, y) => {
+                let dst = usize::from(dst);
+                let (q, r) = div_rem(st[usize::from(x)], st[usize::from(y)]);
+                set_flags(&mut cfl, r);
+                st[dst] = r;
+                aux = q;
+                MemAccess::None
+            }
+            Inst::Cmp(x, y) => {
+                let x = usize::from(x);
+                let y = val(&st, y);
+                let (v, borrow) = st[x].overflowing_sub(y);
+                let (_, o) = (st[x] as SWord).overflowing_sub(y as SWord);
+                set_flags_cv(&mut cfl, v, !borrow, o);
+                MemAccess::None
+            }
+            Inst::Tst(x, y) => {
+                let x = usize::from(x);
+                let y = usize::from(y);
+                set_flags(&mut cfl, st[x] & st[y]);
+                MemAccess::None
+            }
             Inst::Mov(dst, v) => {
                 let dst = usize::from(dst);
-                let v = match v {
-                    Val::Reg(src) => st[usize::from(src)],
-                    Val::Const(c) => c,
+                let v = val(&st, v);
+                set_flags(&mut cfl, v);
+                st[dst] = v;
+                MemAccess::None
+            }
+            Inst::Csel(dst, x, y, cc) => {
+                let dst = usize::from(dst);
+                let v = if holds(cc, &cfl) {
+                    st[usize::from(x)]
+                } else {
+                    st[usize::from(y)]
                 };
                 set_flags(&mut cfl, v);
                 st[dst] = v;
@@ -190,7 +357,8 @@ pub fn interpret(
                 let dst = usize::from(dst);
                 let src = usize::from(src);
                 let addr = st[src];
-                let val = mem[&addr];
+                check_access(prog, addr, false)?;
+                let val = *mem.get(&addr).ok_or("reading uninitialized memory")?;
                 set_flags(&mut cfl, val);
                 st[dst] = val;
                 MemAccess::Read { addr, val }
@@ -199,42 +367,123 @@ pub fn interpret(
                 let dst = usize::from(dst);
                 let src = usize::from(src);
                 let addr = st[dst];
+                check_store(addr)?;
                 let val = st[src];
                 //set_flags(&mut cfl, addr);
+                let old = mem.insert(addr, val).unwrap_or(0);
+                MemAccess::Write { addr, old, val }
+            }
+            Inst::Ldrb(dst, src) => {
+                let dst = usize::from(dst);
+                let src = usize::from(src);
+                let (addr, shift) = (st[src] / WORD_BYTES, 8 * (st[src] % WORD_BYTES));
+                check_access(prog, addr, false)?;
+                let val = *mem.get(&addr).ok_or("reading uninitialized memory")?;
+                let v = (val >> shift) & 0xFF;
+                set_flags(&mut cfl, v);
+                st[dst] = v;
+                MemAccess::Read { addr, val }
+            }
+            Inst::Strb(dst, src) => {
+                let dst = usize::from(dst);
+                let src = usize::from(src);
+                let (addr, shift) = (st[dst] / WORD_BYTES, 8 * (st[dst] % WORD_BYTES));
+                check_store(addr)?;
+                let old = mem.get(&addr).copied().unwrap_or(0);
+                let val = (old & !(0xFF << shift)) | ((st[src] & 0xFF) << shift);
+                mem.insert(addr, val);
+                MemAccess::Write { addr, old, val }
+            }
+            Inst::Ldrh(dst, src) => {
+                let dst = usize::from(dst);
+                let src = usize::from(src);
+                if !st[src].is_multiple_of(2) {
+                    return Err("unaligned halfword access");
+                }
+                let (addr, shift) = (st[src] / WORD_BYTES, 8 * (st[src] % WORD_BYTES));
+                check_access(prog, addr, false)?;
+                let val = *mem.get(&addr).ok_or("reading uninitialized memory")?;
+                let v = (val >> shift) & 0xFFFF;
+                set_flags(&mut cfl, v);
+                st[dst] = v;
+                MemAccess::Read { addr, val }
+            }
+            Inst::Strh(dst, src) => {
+                let dst = usize::from(dst);
+                let src = usize::from(src);
+                if !st[dst].is_multiple_of(2) {
+                    return Err("unaligned halfword access");
+                }
+                let (addr, shift) = (st[dst] / WORD_BYTES, 8 * (st[dst] % WORD_BYTES));
+                check_store(addr)?;
+                let old = mem.get(&addr).copied().unwrap_or(0);
+                let val = (old & !(0xFFFF << shift)) | ((st[src] & 0xFFFF) << shift);
                 mem.insert(addr, val);
-                MemAccess::Write { addr, val }
+                MemAccess::Write { addr, old, val }
             }
-            Inst::B(cond, r) => {
+            Inst::B(cond, v) => {
                 let pc_ = match cond {
-                    Some(cond) => match cond {
-                        Cond::Z => {
-                            if cfl[0] {
-                                st[usize::from(r)]
-                            } else {
-                                st[pc] + 1
-                            }
-                        }
-                    },
-                    None => st[usize::from(r)],
+                    Some(cc) if !holds(cc, &cfl) => st[pc] + 1,
+                    _ => val(&st, v),
                 };
                 set_flags(&mut cfl, pc_);
                 st[pc] = pc_;
                 i = fetch(prog, st[pc])?;
-                sts.push(record(&st, &cfl, MemAccess::None, sts.len()));
+                sts.push(record(&st, &cfl, io, MemAccess::None, 0, sts.len()));
                 continue;
             }
-            Inst::Ret(v) => {
-                let v = match v {
-                    Val::Reg(r) => st[usize::from(r)],
-                    Val::Const(c) => c,
+            Inst::BRel(cond, offset) => {
+                let pc_ = match cond {
+                    Some(cc) if !holds(cc, &cfl) => st[pc] + 1,
+                    // Sign extends the offset to the word size
+                    _ => st[pc].wrapping_add(offset as Word),
                 };
+                set_flags(&mut cfl, pc_);
+                st[pc] = pc_;
+                i = fetch(prog, st[pc])?;
+                sts.push(record(&st, &cfl, io, MemAccess::None, 0, sts.len()));
+                continue;
+            }
+            Inst::Bl(v) => {
+                // The target is read before LR is set, e.g for BL LR
+                let target = val(&st, v);
+                let lr = usize::from(LR);
+                st[lr] = st[pc] + 1;
+                set_flags(&mut cfl, st[lr]);
+                st[pc] = target;
+                i = fetch(prog, st[pc])?;
+                sts.push(record(&st, &cfl, io, MemAccess::None, 0, sts.len()));
+                continue;
+            }
+            Inst::Ret(v) => {
+                let v = val(&st, v);
                 set_flags(&mut cfl, v);
                 // machine returns in r1
                 st[1] = v;
                 //inc_pc(&mut st);
-                sts.push(record(&st, &cfl, MemAccess::None, sts.len()));
+                sts.push(record(&st, &cfl, io, MemAccess::None, 0, sts.len()));
                 break v;
             }
+            Inst::Read(dst, tape) => {
+                let dst = usize::from(dst);
+                let v = match tape {
+                    Tape::Public => {
+                        io.0 += 1;
+                        public.next().ok_or("public tape exhausted")?
+                    }
+                    Tape::Private => private.next().ok_or("private tape exhausted")?,
+                };
+                set_flags(&mut cfl, v);
+                st[dst] = v;
+                MemAccess::None
+            }
+            Inst::Out(r) => {
+                let v = st[usize::from(r)];
+                set_flags(&mut cfl, v);
+                io.1 += 1;
+                outputs.push(v);
+                MemAccess::None
+            }
             Inst::Print(r) => {
                 let x = st[usize::from(r)];
                 let x = format!("{:#10x}", x);
@@ -245,19 +494,64 @@ pub fn interpret(
         };
         inc_pc(&mut st);
         i = fetch(prog, st[pc])?;
-        sts.push(record(&st, &cfl, ma, sts.len()));
+        sts.push(record(&st, &cfl, io, ma, aux, sts.len()));
         if t.is_some_and(|t| sts.len() >= t) {
             return Err("time bound exceeded");
         }
     };
 
     // dbg!(&st, &cfl);
-    Ok((res, sts))
+    Ok((res, outputs, sts))
 }
 
 fn fetch(prog: &Prog, pc: Word) -> Res<&Inst> {
     let pc: usize = pc.try_into().unwrap();
-    prog.get(pc).ok_or("stuck fetching")
+    prog.code.get(pc).ok_or("stuck fetching")
+}
+
+/// Checks that prog may access the memory word at addr, i.e that
+/// the address isn't the highest address (see mem_circ of the
+/// reduction), and that it is in a region of prog, which must be
+/// writable if write is set, unless prog has no regions.
+fn check_access(prog: &Prog, addr: Word, write: bool) -> Res<()> {
+    if addr == Word::MAX {
+        return Err("address out of range");
+    }
+    if prog.regions.is_empty() {
+        return Ok(());
+    }
+    let perms = prog
+        .regions
+        .iter()
+        .filter(|r| r.contains(addr))
+        .map(|r| r.perm)
+        .collect::<Vec<_>>();
+    if perms.is_empty() {
+        return Err("memory access outside the regions");
+    }
+    if write && !perms.contains(&Perm::ReadWrite) {
+        return Err("writing to read-only memory");
+    }
+    Ok(())
+}
+
+/// Returns the quotient and remainder of x divided by y. Dividing by
+/// zero gives the quotient 0 and the remainder x, as checked by the
+/// circuit.
+fn div_rem(x: Word, y: Word) -> (Word, Word) {
+    match y {
+        0 => (0, x),
+        _ => (x / y, x % y),
+    }
+}
+
+/// Value of v, i.e the value in the register or the constant
+#[inline]
+fn val(st: &Store, v: Val) -> Word {
+    match v {
+        Val::Reg(r) => st[usize::from(r)],
+        Val::Const(c) => c,
+    }
 }
 
 #[inline]
@@ -265,19 +559,50 @@ fn inc_pc(st: &mut Store) {
     st[usize::from(PC)] += 1
 }
 
-/// Add arguments to addresses 0, 1, ..., args.len()-1 in mem
-fn init_mem(args: Vec<Word>) -> Mem {
+/// Add arguments to addresses 0, 1, ..., args.len()-1 in mem, the
+/// encoded program to addresses ADR_CODE, ADR_CODE + 1, ... if code
+/// is set, and the data image of prog.
+///
+/// Instructions are still fetched from prog, so programs writing to
+/// their own code are not supported (see interpret_von_neumann).
+fn init_mem(prog: &Prog, args: Vec<Word>, code: bool) -> Res<Mem> {
+    // The circuit adds 1 to addresses (see mem_circ), so the highest
+    // address can't hold data or end a region
+    let data_max = prog.data.iter().any(|(k, _)| *k == Word::MAX);
+    if data_max || prog.regions.iter().any(|r| r.end == Word::MAX) {
+        return Err("data image or regions reach the highest address");
+    }
     let mut mem = Mem::new();
     for (k, v) in args.into_iter().enumerate() {
         let k = k.try_into().unwrap();
-        assert!(mem.insert(k, v).is_none())
+        mem.insert(k, v);
+    }
+    if code {
+        for (k, v) in code_words(prog).into_iter().enumerate() {
+            let k = ADR_CODE + Word::try_from(k).unwrap();
+            mem.insert(k, v);
+        }
     }
-    mem
+    for (k, v) in &prog.data {
+        if mem.insert(*k, *v).is_some() {
+            return Err("data image overlaps the arguments, the program or itself");
+        }
+    }
+    Ok(mem)
+}
+
+/// Returns the words of the encoded program, i.e the high and low
+/// word of each instruction, in the order they are stored in memory
+pub fn code_words(prog: &Prog) -> Vec<Word> {
+    encode(prog)
+        .into_iter()
+        .flat_map(|i| [Word::from((i >> 32) as u32), Word::from(i as u32)])
+        .collect()
 }
 
 #[inline]
 fn init_store() -> Store {
-    [0; N_REG]
+    [0; MAX_N_REG]
 }
 
 #[inline]
@@ -285,19 +610,70 @@ fn init_cflags() -> Cflags {
     [false; N_CFL]
 }
 
-/// Sets conditional flags:
-///  - cfl[0] = 1  iff  v == 0  (i.e cfl[0] is the flag Z)
+/// Returns the shift amount x modulo the word size
+#[inline]
+fn shamt(x: Word) -> u32 {
+    u32::from((x % Word::from(Word::BITS)) as u8)
+}
+
+/// Sets conditional flags of an instruction that is not an addition
+/// or subtraction:
+///  - Z = 1  iff  v == 0
+///  - N = 1  iff  the most significant bit of v is set
+///  - C = V = 0
 #[inline]
 fn set_flags(cfl: &mut Cflags, v: Word) {
-    cfl[0] = v == 0
+    set_flags_cv(cfl, v, false, false)
+}
+
+/// Sets conditional flags like set_flags, but with explicit values
+/// of the carry flag C and the overflow flag V.
+#[inline]
+fn set_flags_cv(cfl: &mut Cflags, v: Word, c: bool, o: bool) {
+    cfl[Cond::Z as usize] = v == 0;
+    cfl[Cond::C as usize] = c;
+    cfl[Cond::N as usize] = v >> (Word::BITS - 1) == 1;
+    cfl[Cond::V as usize] = o;
+}
+
+/// Returns whether the condition cc holds for the flags cfl
+fn holds(cc: CondCode, cfl: &Cflags) -> bool {
+    let z = cfl[Cond::Z as usize];
+    let c = cfl[Cond::C as usize];
+    let n = cfl[Cond::N as usize];
+    let v = cfl[Cond::V as usize];
+    match cc {
+        CondCode::Eq => z,
+        CondCode::Ne => !z,
+        CondCode::Hs => c,
+        CondCode::Lo => !c,
+        CondCode::Mi => n,
+        CondCode::Pl => !n,
+        CondCode::Vs => v,
+        CondCode::Vc => !v,
+        CondCode::Hi => c && !z,
+        CondCode::Ls => !c || z,
+        CondCode::Ge => n == v,
+        CondCode::Lt => n != v,
+        CondCode::Gt => !z && n == v,
+        CondCode::Le => z || n != v,
+    }
 }
 
 /// Records the current local state of the program execution
 #[inline]
-fn record(st: &Store, cfl: &Cflags, ma: MemAccess, step: usize) -> LocalStateAug {
+fn record(
+    st: &Store,
+    cfl: &Cflags,
+    io: (Word, Word),
+    ma: MemAccess,
+    aux: Word,
+    step: usize,
+) -> LocalStateAug {
     LocalStateAug {
-        st: (*st, *cfl),
+        st: (*st, *cfl, io.0, io.1),
         ma,
+        aux,
         step: u64::try_from(step).unwrap(),
     }
 }
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use strum::EnumCount;
 use strum_macros::{EnumCount, EnumIter};
 
@@ -7,16 +9,46 @@ use strum_macros::{EnumCount, EnumIter};
 ///
 /// v0: Harvard architecture, i.e code and data are is seperate
 /// memories
+///
+/// v1: The program can also be proven in a Von Neumann mode, where
+/// it is loaded into memory at ADR_CODE
 
+// The word size is a parameter of the machine, which is 32 bits, or
+// 64 bits with the word64 feature. The reduction and the backend
+// only support 32 bit words so far.
+#[cfg(not(feature = "word64"))]
 pub type Word = u32;
+#[cfg(feature = "word64")]
+pub type Word = u64;
+
+// Signed word, and double word for the upper half of products
+#[cfg(not(feature = "word64"))]
+pub type SWord = i32;
+#[cfg(not(feature = "word64"))]
+pub type DWord = u64;
+#[cfg(feature = "word64")]
+pub type SWord = i64;
+#[cfg(feature = "word64")]
+pub type DWord = u128;
+
+// Number of bytes in a word
+pub const WORD_BYTES: Word = Word::BITS as Word / 8;
 
 pub type Reg = u8;
 
-// Registers are PC, R1, ..., R15
-pub const N_REG: usize = 16;
-// Condition flags are Z
+// Registers are PC, R1, ..., R(n-1), where the register count n is a
+// parameter of the machine, chosen among N_REGS (see n_reg)
+pub const N_REGS: [usize; 4] = [4, 8, 16, 32];
+pub const MAX_N_REG: usize = 32;
+// Condition flags are Z, C, N and V
 pub const N_CFL: usize = Cond::COUNT;
 
+// Address of the program in memory. Instruction i is stored as the
+// high and low word of its encoding at ADR_CODE + 2i and ADR_CODE +
+// 2i + 1
+pub const ADR_CODE: Word = 0x8000_0000;
+
+#[allow(dead_code)]
 pub mod reg {
     use super::Reg;
 
@@ -27,40 +59,145 @@ pub mod reg {
     pub const R4: Reg = 4;
     pub const R5: Reg = 5;
     pub const R6: Reg = 6;
+    // Link register, holds the return address of subroutine calls
+    pub const LR: Reg = 15;
     // pub const R7: Reg = 7;
     // pub const R8: Reg = 8;
     // pub const R9: Reg = 9;
 }
 
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum Inst {
     // Bitwise operations
-    And(Reg, Reg, Reg),
-    Xor(Reg, Reg, Reg),
+    And(Reg, Reg, Val),
+    Xor(Reg, Reg, Val),
+    Orr(Reg, Reg, Reg),
+    Mvn(Reg, Reg),        // bitwise not
     Shr(Reg, Word, Reg),  // todo: Change Word to 5 bit type
+    Shl(Reg, Word, Reg),  // todo: Change Word to 5 bit type
     Rotr(Reg, Word, Reg), // todo: Change Word to 5 bit type
+    // Shift amount in register (modulo the word size)
+    ShrR(Reg, Reg, Reg),
+    ShlR(Reg, Reg, Reg),
+    RotrR(Reg, Reg, Reg),
     // Integer operations
-    Add(Reg, Reg, Reg),
-    Sub(Reg, Reg, Reg),
+    Add(Reg, Reg, Val),
+    Sub(Reg, Reg, Val),
+    Mul(Reg, Reg, Reg),   // lower word of product
+    Umulh(Reg, Reg, Reg), // upper word of (unsigned) product
+    // Unsigned division and remainder. Dividing x by zero gives the
+    // quotient 0 and the remainder x.
+    Udiv(Reg, Reg, Reg),
+    Urem(Reg, Reg, Reg),
+    // Comparison (only sets conditional flags)
+    Cmp(Reg, Val), // flags of Sub
+    Tst(Reg, Reg), // flags of And
     // Move
     Mov(Reg, Val),
+    // Conditional select: dst = x if the condition holds, else y
+    Csel(Reg, Reg, Reg, CondCode),
     // Memory access
     Ldr(Reg, Reg),
     Str(Reg, Reg),
+    // Byte and halfword memory access, addressing bytes in little
+    // endian order, i.e byte address a is byte a % WORD_BYTES of the
+    // word at address a / WORD_BYTES. Halfword addresses must be
+    // even.
+    Ldrb(Reg, Reg),
+    Strb(Reg, Reg),
+    Ldrh(Reg, Reg),
+    Strh(Reg, Reg),
     // Branching (unconditional and conditional)
-    B(Option<Cond>, Reg),
+    B(Option<CondCode>, Val),
+    // Branching relative to the address of the instruction
+    BRel(Option<CondCode>, i32),
+    // Subroutine call: branch and store the address of the next
+    // instruction in LR. Subroutines return by branching to LR.
+    Bl(Val),
     // Halting
     Ret(Val),
+    // Input: read the next value of a tape
+    Read(Reg, Tape),
+    // Output: append the value of a register to the public outputs
+    // of the program, which are revealed by the proof along with the
+    // value returned
+    Out(Reg),
     // Debugging
     Print(Reg),
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Formats an instruction in the syntax of the assembler, see
+/// asm::assemble
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Inst::*;
+        let r = |r: &Reg| match *r {
+            reg::PC => "pc".to_string(),
+            reg::LR => "lr".to_string(),
+            r => format!("r{r}"),
+        };
+        let v = |v: &Val| match v {
+            Val::Reg(x) => r(x),
+            Val::Const(c) => c.to_string(),
+        };
+        let cc = |cc: &Option<CondCode>| match cc {
+            None => String::new(),
+            Some(cc) => format!(".{cc}"),
+        };
+        match self {
+            And(dst, x, y) => write!(f, "AND {}, {}, {}", r(dst), r(x), v(y)),
+            Xor(dst, x, y) => write!(f, "XOR {}, {}, {}", r(dst), r(x), v(y)),
+            Orr(dst, x, y) => write!(f, "ORR {}, {}, {}", r(dst), r(x), r(y)),
+            Mvn(dst, x) => write!(f, "MVN {}, {}", r(dst), r(x)),
+            Shr(dst, y, x) => write!(f, "SHR {}, {}, {y}", r(dst), r(x)),
+            Shl(dst, y, x) => write!(f, "SHL {}, {}, {y}", r(dst), r(x)),
+            Rotr(dst, y, x) => write!(f, "ROTR {}, {}, {y}", r(dst), r(x)),
+            ShrR(dst, y, x) => write!(f, "SHR {}, {}, {}", r(dst), r(x), r(y)),
+            ShlR(dst, y, x) => write!(f, "SHL {}, {}, {}", r(dst), r(x), r(y)),
+            RotrR(dst, y, x) => write!(f, "ROTR {}, {}, {}", r(dst), r(x), r(y)),
+            Add(dst, x, y) => write!(f, "ADD {}, {}, {}", r(dst), r(x), v(y)),
+            Sub(dst, x, y) => write!(f, "SUB {}, {}, {}", r(dst), r(x), v(y)),
+            Mul(dst, x, y) => write!(f, "MUL {}, {}, {}", r(dst), r(x), r(y)),
+            Umulh(dst, x, y) => write!(f, "UMULH {}, {}, {}", r(dst), r(x), r(y)),
+            Udiv(dst, x, y) => write!(f, "UDIV {}, {}, {}", r(dst), r(x), r(y)),
+            Urem(dst, x, y) => write!(f, "UREM {}, {}, {}", r(dst), r(x), r(y)),
+            Cmp(x, y) => write!(f, "CMP {}, {}", r(x), v(y)),
+            Tst(x, y) => write!(f, "TST {}, {}", r(x), r(y)),
+            Mov(dst, y) => write!(f, "MOV {}, {}", r(dst), v(y)),
+            Csel(dst, x, y, c) => write!(f, "CSEL {}, {}, {}, {c}", r(dst), r(x), r(y)),
+            Ldr(dst, src) => write!(f, "LDR {}, {}", r(dst), r(src)),
+            Str(dst, src) => write!(f, "STR {}, {}", r(dst), r(src)),
+            Ldrb(dst, src) => write!(f, "LDRB {}, {}", r(dst), r(src)),
+            Strb(dst, src) => write!(f, "STRB {}, {}", r(dst), r(src)),
+            Ldrh(dst, src) => write!(f, "LDRH {}, {}", r(dst), r(src)),
+            Strh(dst, src) => write!(f, "STRH {}, {}", r(dst), r(src)),
+            B(c, x) => write!(f, "B{} {}", cc(c), v(x)),
+            BRel(c, offset) => write!(f, "BREL{} {offset:+}", cc(c)),
+            Bl(x) => write!(f, "BL {}", v(x)),
+            Ret(x) => write!(f, "RET {}", v(x)),
+            Read(dst, Tape::Public) => write!(f, "READ {}, public", r(dst)),
+            Read(dst, Tape::Private) => write!(f, "READ {}, private", r(dst)),
+            Out(x) => write!(f, "OUT {}", r(x)),
+            Print(x) => write!(f, "PRINT {}", r(x)),
+        }
+    }
+}
+
+/// Input tapes of a program. The public tape is known by both prover
+/// and verifier, while the private tape is only known by the prover,
+/// e.g used for advice (hints) that the program checks.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Tape {
+    Private = 0,
+    Public = 1,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum Val {
     // Value in register
     Reg(Reg),
-    // Const
+    // Const (encoded in 32 bits, also with 64 bit words)
     Const(Word),
 }
 
@@ -68,6 +205,146 @@ pub enum Val {
 pub enum Cond {
     // Set when arithmetic intr. resulted in zero
     Z,
+    // Set when an addition produced a carry, or a subtraction did
+    // not borrow
+    C,
+    // Set when the most significant bit of the result is set
+    N,
+    // Set when an addition or subtraction overflowed as a signed
+    // operation
+    V,
+}
+
+/// Conditions of conditional branches. Encoded as in ARM, i.e the
+/// least significant bit negates the condition.
+#[allow(dead_code)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, EnumIter)]
+pub enum CondCode {
+    // Equal: Z
+    Eq = 0b0000,
+    // Not equal: !Z
+    Ne = 0b0001,
+    // Unsigned higher or same: C
+    Hs = 0b0010,
+    // Unsigned lower: !C
+    Lo = 0b0011,
+    // Negative: N
+    Mi = 0b0100,
+    // Positive or zero: !N
+    Pl = 0b0101,
+    // Overflow: V
+    Vs = 0b0110,
+    // No overflow: !V
+    Vc = 0b0111,
+    // Unsigned higher: C and !Z
+    Hi = 0b1000,
+    // Unsigned lower or same: !C or Z
+    Ls = 0b1001,
+    // Signed greater than or equal: N = V
+    Ge = 0b1010,
+    // Signed less than: N != V
+    Lt = 0b1011,
+    // Signed greater than: !Z and N = V
+    Gt = 0b1100,
+    // Signed less than or equal: Z or N != V
+    Le = 0b1101,
+}
+
+impl fmt::Display for CondCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{self:?}").to_uppercase())
+    }
+}
+
+/// A program consists of its instructions and its data image, i.e
+/// the initial values of memory words at addresses known to both the
+/// prover and the verifier, such as tables of constants. Loading the
+/// data image takes no steps of the execution.
+///
+/// The program may also declare the regions of memory it accesses,
+/// e.g read-only constants and a read-write heap and stack. Then
+/// every memory access must be in a region, and every store in a
+/// writable region. A program without regions may access all of
+/// memory, except the highest address, which is reserved by the
+/// reduction.
+#[derive(Debug, Default)]
+pub struct Prog {
+    pub code: Vec<Inst>,
+    /// Pairs of address and value
+    pub data: Vec<(Word, Word)>,
+    pub regions: Vec<Region>,
+}
+
+/// Memory words at addresses start, start + 1, ..., end - 1, i.e
+/// a region never contains the highest address
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Region {
+    pub start: Word,
+    pub end: Word,
+    pub perm: Perm,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Perm {
+    Read,
+    ReadWrite,
+}
+
+impl Region {
+    pub fn contains(&self, adr: Word) -> bool {
+        self.start <= adr && adr < self.end
+    }
 }
 
-pub type Prog = Vec<Inst>;
+/// Returns the smallest register count of N_REGS that has all the
+/// registers used by prog. Each step of the execution only carries
+/// this many registers.
+pub fn n_reg(prog: &Prog) -> usize {
+    let max = prog.code.iter().flat_map(regs).max().unwrap_or(reg::PC);
+    *N_REGS
+        .iter()
+        .find(|n| usize::from(max) < **n)
+        .expect("register out of range")
+}
+
+/// Returns the registers used by an instruction
+fn regs(i: &Inst) -> Vec<Reg> {
+    use Inst::*;
+    let val = |v: &Val| match v {
+        Val::Reg(r) => vec![*r],
+        Val::Const(_) => vec![],
+    };
+    match i {
+        And(dst, x, y) | Xor(dst, x, y) | Add(dst, x, y) | Sub(dst, x, y) => {
+            [vec![*dst, *x], val(y)].concat()
+        }
+        Orr(dst, x, y)
+        | ShrR(dst, x, y)
+        | ShlR(dst, x, y)
+        | RotrR(dst, x, y)
+        | Mul(dst, x, y)
+        | Umulh(dst, x, y)
+        | Udiv(dst, x, y)
+        | Urem(dst, x, y)
+        | Csel(dst, x, y, _) => vec![*dst, *x, *y],
+        Mvn(dst, x)
+        | Shr(dst, _, x)
+        | Shl(dst, _, x)
+        | Rotr(dst, _, x)
+        | Tst(dst, x)
+        | Ldr(dst, x)
+        | Str(dst, x)
+        | Ldrb(dst, x)
+        | Strb(dst, x)
+        | Ldrh(dst, x)
+        | Strh(dst, x) => vec![*dst, *x],
+        Cmp(x, y) | Mov(x, y) => [vec![*x], val(y)].concat(),
+        B(_, x) => val(x),
+        // The machine returns in R1
+        Ret(x) => [vec![reg::R1], val(x)].concat(),
+        Bl(x) => [vec![reg::LR], val(x)].concat(),
+        BRel(..) => vec![],
+        Read(dst, _) | Out(dst) | Print(dst) => vec![*dst],
+    }
+}
@@ -0,0 +1,248 @@
+use utils::sha256;
+
+use crate::miniram::encode::{decode, encode};
+use crate::miniram::interpreter::Res;
+use crate::miniram::lang::*;
+
+/// Object files of MiniRAM programs, for saving a program and loading
+/// the exact same program in the prover and the verifier.
+///
+/// An object file is a sequence of 32 bit big-endian words:
+///
+///   magic, i.e "MRAM"
+///   version of the format (VERSION)
+///   word size of the machine in bits
+///   register count of the program (see n_reg)
+///   entry point, i.e the initial pc
+///   n, followed by the high and low word of n encoded instructions
+///   n, followed by n words of the data image as (adr, value)
+///   n, followed by n memory regions as (start, end, perm), where
+///      perm is 0 for read-only and 1 for read-write
+///   n, followed by n labels as (len, name, value), where the name is
+///      len bytes of UTF-8 padded with zeros to whole words
+///   SHA-256 digest of the preceding words, i.e of the preceding
+///      bytes of the file
+///
+/// Addresses and values are 64 bits, i.e a high and a low word, for
+/// both word sizes.
+pub const VERSION: u32 = 1;
+
+const MAGIC: u32 = u32::from_be_bytes(*b"MRAM");
+
+/// Number of words of the digest at the end of the file
+const N_HASH: usize = 8;
+
+/// Labels of a program and their values, see asm::Asm
+pub type Labels = Vec<(String, Word)>;
+
+/// Returns the object file of prog with the given labels
+pub fn write(prog: &Prog, labels: &[(String, Word)]) -> Vec<u8> {
+    let len = |n: usize| u32::try_from(n).unwrap();
+    let mut ws = vec![MAGIC, VERSION, Word::BITS, len(n_reg(prog)), 0];
+    let code = encode(prog);
+    ws.push(len(code.len()));
+    ws.extend(code.iter().flat_map(|i| split(*i)));
+    ws.push(len(prog.data.len()));
+    for (adr, v) in &prog.data {
+        ws.extend(split(u64::from(*adr)));
+        ws.extend(split(u64::from(*v)));
+    }
+    ws.push(len(prog.regions.len()));
+    for r in &prog.regions {
+        ws.extend(split(u64::from(r.start)));
+        ws.extend(split(u64::from(r.end)));
+        ws.push(u32::from(r.perm == Perm::ReadWrite));
+    }
+    ws.push(len(labels.len()));
+    for (name, v) in labels {
+        let mut bytes = name.as_bytes().to_vec();
+        ws.push(len(bytes.len()));
+        bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+        ws.extend(words(&bytes));
+        ws.extend(split(u64::from(*v)));
+    }
+    ws.extend(sha256::digest(&ws));
+    ws.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+/// Reads the object file in bytes, and returns the program and its
+/// labels, or an error if the file is malformed or was written for
+/// another machine
+pub fn read(bytes: &[u8]) -> Res<(Prog, Labels)> {
+    if !bytes.len().is_multiple_of(4) || bytes.len() < 4 * N_HASH {
+        return Err("truncated object file");
+    }
+    let ws = words(bytes);
+    let n = ws.len() - N_HASH;
+    if sha256::digest(&ws[..n]) != ws[n..] {
+        return Err("content hash mismatch");
+    }
+
+    let mut r = Reader(ws[..n].iter());
+    if r.u32()? != MAGIC {
+        return Err("not a MiniRAM object file");
+    }
+    if r.u32()? != VERSION {
+        return Err("unsupported version");
+    }
+    if r.u32()? != Word::BITS {
+        return Err("word size mismatch");
+    }
+    let n_reg_ = r.u32()?;
+    // The machine starts at pc 0
+    if r.u32()? != 0 {
+        return Err("unsupported entry point");
+    }
+    let code = (0..r.u32()?).map(|_| r.u64()).collect::<Res<Vec<_>>>()?;
+    let mut prog = decode(&code).map_err(|(_, e)| e)?;
+    if usize::try_from(n_reg_).unwrap() != n_reg(&prog) {
+        return Err("register count mismatch");
+    }
+    for _ in 0..r.u32()? {
+        prog.data.push((r.word()?, r.word()?));
+    }
+    for _ in 0..r.u32()? {
+        let (start, end) = (r.word()?, r.word()?);
+        let perm = match r.u32()? {
+            0 => Perm::Read,
+            1 => Perm::ReadWrite,
+            _ => return Err("invalid permission"),
+        };
+        prog.regions.push(Region { start, end, perm });
+    }
+    let mut labels = vec![];
+    for _ in 0..r.u32()? {
+        let len = usize::try_from(r.u32()?).unwrap();
+        if len.div_ceil(4) > r.0.len() {
+            return Err("truncated object file");
+        }
+        let bytes = (0..len.div_ceil(4))
+            .map(|_| r.u32().map(u32::to_be_bytes))
+            .collect::<Res<Vec<_>>>()?;
+        let name = bytes.concat()[..len].to_vec();
+        let name = String::from_utf8(name).map_err(|_| "invalid label")?;
+        labels.push((name, r.word()?));
+    }
+    if r.0.next().is_some() {
+        return Err("trailing words in object file");
+    }
+    Ok((prog, labels))
+}
+
+/// Returns the content hash of the object file in bytes, i.e the
+/// SHA-256 digest at the end of the file, in hex
+pub fn hash(bytes: &[u8]) -> String {
+    let n = bytes.len().saturating_sub(4 * N_HASH);
+    bytes[n..].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Returns the big-endian words of bytes, whose length is a multiple
+/// of 4
+fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks(4)
+        .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+        .collect()
+}
+
+/// Returns the high and low word of x
+fn split(x: u64) -> [u32; 2] {
+    [(x >> 32) as u32, x as u32]
+}
+
+/// Reads the words of an object file in order
+struct Reader<'a>(std::slice::Iter<'a, u32>);
+
+impl Reader<'_> {
+    fn u32(&mut self) -> Res<u32> {
+        self.0.next().copied().ok_or("truncated object file")
+    }
+    fn u64(&mut self) -> Res<u64> {
+        Ok(u64::from(self.u32()?) << 32 | u64::from(self.u32()?))
+    }
+    fn word(&mut self) -> Res<Word> {
+        Word::try_from(self.u64()?).map_err(|_| "word out of range")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read, write, MAGIC, N_HASH, VERSION};
+    use crate::miniram::asm::assemble;
+    use crate::miniram::lang::Perm;
+    use crate::miniram::programs;
+    use utils::sha256;
+
+    /// Returns bytes with the digest at the end replaced by the digest
+    /// of the other words, i.e an object file with a valid hash
+    fn rehash(bytes: &[u8]) -> Vec<u8> {
+        let n = bytes.len() - 4 * N_HASH;
+        let ws = super::words(&bytes[..n]);
+        let hash = sha256::digest(&ws);
+        ws.iter()
+            .chain(&hash)
+            .flat_map(|w| w.to_be_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let src = "
+            .region tab, end, r
+            .region 0, 16, rw
+            .data 100
+            tab: .word 3, 5, -1
+            end:
+            .text
+            loop:   LDR r3, r1
+                    SUB r1, r1, 1
+                    BREL.NE loop
+                    RET r3
+        ";
+        let asm = assemble(src).unwrap();
+        let bytes = write(&asm.prog, &asm.labels);
+        let (prog, labels) = read(&bytes).unwrap();
+        assert_eq!(prog.code, asm.prog.code);
+        assert_eq!(prog.data, asm.prog.data);
+        assert_eq!(prog.regions, asm.prog.regions);
+        assert_eq!(prog.regions[1].perm, Perm::ReadWrite);
+        assert_eq!(labels, asm.labels);
+
+        let prog = programs::mul_eq();
+        let (prog_, labels) = read(&write(&prog, &[])).unwrap();
+        assert_eq!(prog_.code, prog.code);
+        assert!(prog_.data.is_empty() && labels.is_empty());
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = write(&programs::mul_eq(), &[("main".to_string(), 0)]);
+        assert_eq!(&bytes[..4], MAGIC.to_be_bytes());
+        assert_eq!(&bytes[4..8], VERSION.to_be_bytes());
+
+        let mut tampered = bytes.clone();
+        tampered[40] ^= 1;
+        assert_eq!(read(&tampered).unwrap_err(), "content hash mismatch");
+        assert_eq!(read(&bytes[1..]).unwrap_err(), "truncated object file");
+        assert_eq!(read(&[]).unwrap_err(), "truncated object file");
+
+        for (i, msg) in [
+            (0, "not a MiniRAM object file"),
+            (7, "unsupported version"),
+            (11, "word size mismatch"),
+            (19, "unsupported entry point"),
+        ] {
+            let mut b = bytes.clone();
+            b[i] ^= 1;
+            assert_eq!(read(&rehash(&b)).unwrap_err(), msg);
+        }
+
+        let mut b = bytes[..bytes.len() - 4 * N_HASH].to_vec();
+        b.extend([0; 4]);
+        b.extend([0; 4 * N_HASH]);
+        assert_eq!(
+            read(&rehash(&b)).unwrap_err(),
+            "trailing words in object file"
+        );
+    }
+}
@@ -1,16 +1,18 @@
+#[cfg(not(feature = "word64"))]
 use utils::sha256;
 
 use crate::miniram::builder::*;
 use crate::miniram::lang::{reg::*, Prog, Reg};
+#[cfg(any(test, not(feature = "word64")))]
+use crate::miniram::lang::{CondCode, Tape};
+#[cfg(test)]
+use crate::miniram::lang::{SWord, Word};
 
 const RES: Reg = R3;
 
 fn mul_() -> Builder {
     let x = R1;
     let y = R2;
-    let one = R4;
-    let top = R5;
-    let bot = R6;
 
     Builder::new()
         //  fetch args from memory
@@ -18,18 +20,10 @@ fn mul_() -> Builder {
         .ldr(x, x)
         .mov_c(y, 1)
         .ldr(y, y)
-        //  move one to tmp register
-        .mov_c(one, 1)
         .mov_r(RES, y)
-        // loop:
-        //  prepare address to jump to if i = 1
-        .mov_r(top, PC)
-        .mov_c(bot, 5)
-        .add(bot, PC, bot)
-        .sub(x, x, one)
-        .b_z(bot)
-        .add(RES, RES, y)
-        .b(top)
+        //  add y to RES x - 1 times
+        .sub_c(x, x, 1)
+        .while_nz(x, |b| b.add(RES, RES, y).sub_c(x, x, 1))
 }
 
 /// Computes x * y
@@ -52,6 +46,25 @@ pub fn mul_eq() -> Prog {
         .build()
 }
 
+/// Computes x * y - z using the MUL instruction
+pub fn mul_native_eq() -> Prog {
+    let x = R1;
+    let y = R2;
+    let z = R4;
+    Builder::new()
+        //  fetch args from memory
+        .mov_c(x, 0)
+        .ldr(x, x)
+        .mov_c(y, 1)
+        .ldr(y, y)
+        .mov_c(z, 2)
+        .ldr(z, z)
+        .mul(RES, x, y)
+        .sub(RES, RES, z)
+        .ret_r(RES)
+        .build()
+}
+
 /// RET 0
 pub fn const_0() -> Prog {
     Builder::new().ret_c(0).build()
@@ -95,7 +108,15 @@ pub fn mov42_ret() -> Prog {
 /// MOV r2, b100000000000000000000; RET 0
 #[cfg(test)]
 pub fn mov2pow20_ret0() -> Prog {
-    Builder::new().mov_c(2, 2u32.pow(20)).ret_r(3).build()
+    Builder::new().mov_c(2, 1 << 20).ret_r(3).build()
+}
+
+/// MOV r31, 42
+/// SUB r31, r31, 42
+/// RET r31
+#[cfg(test)]
+pub fn sub42_r31_ret() -> Prog {
+    Builder::new().mov_c(31, 42).sub_c(31, 31, 42).ret_r(31).build()
 }
 
 /// MOV r1, 0
@@ -309,6 +330,456 @@ pub fn b_z_skip() -> Prog {
         .build()
 }
 
+/// MOV r3, 6
+/// MOV r1, x
+/// MOV r2, y
+/// ADD r4, r1, r2   <-- sets flags
+/// B cc r3          <-- skips next instr if cc holds
+/// RET 1
+/// RET 0
+#[cfg(test)]
+pub fn add_b_cond(cc: CondCode, x: Word, y: Word) -> Prog {
+    Builder::new()
+        .mov_c(3, 6)
+        .mov_c(1, x)
+        .mov_c(2, y)
+        .add(4, 1, 2)
+        .b_cond(cc, 3)
+        .ret_c(1)
+        .ret_c(0)
+        .build()
+}
+
+/// MOV r3, 6
+/// MOV r1, x
+/// MOV r2, y
+/// SUB r4, r1, r2   <-- sets flags
+/// B cc r3          <-- skips next instr if cc holds
+/// RET 1
+/// RET 0
+#[cfg(test)]
+pub fn sub_b_cond(cc: CondCode, x: Word, y: Word) -> Prog {
+    Builder::new()
+        .mov_c(3, 6)
+        .mov_c(1, x)
+        .mov_c(2, y)
+        .sub(4, 1, 2)
+        .b_cond(cc, 3)
+        .ret_c(1)
+        .ret_c(0)
+        .build()
+}
+
+/// MOV r1, 5
+/// MOV r2, 2
+/// MOV r3, 6
+/// CMP r1, r2   <-- sets flags of 5 - 2
+/// B HI r3      <-- skips next instr
+/// RET 1
+/// MOV r3, 10
+/// TST r1, r2   <-- sets Z, as 5 & 2 = 0
+/// B Z r3       <-- skips next instr
+/// RET 1
+/// RET 0
+#[cfg(test)]
+pub fn cmp_tst() -> Prog {
+    Builder::new()
+        .mov_c(1, 5)
+        .mov_c(2, 2)
+        .mov_c(3, 6)
+        .cmp(1, 2)
+        .b_cond(CondCode::Hi, 3)
+        .ret_c(1)
+        .mov_c(3, 10)
+        .tst(1, 2)
+        .b_z(3)
+        .ret_c(1)
+        .ret_c(0)
+        .build()
+}
+
+/// MOV r1, 1
+/// MOV r2, 2
+/// CMP r1, r2            <-- sets flags of 1 - 2
+/// CSEL r3, r1, r2, LO   <-- selects r1
+/// CSEL r4, r1, r2, HS   <-- selects r2
+/// SUB r3, r4, r3
+/// SUB r3, r3, 1
+/// RET r3
+#[cfg(test)]
+pub fn csel() -> Prog {
+    Builder::new()
+        .mov_c(1, 1)
+        .mov_c(2, 2)
+        .cmp(1, 2)
+        .csel(3, 1, 2, CondCode::Lo)
+        .csel(4, 1, 2, CondCode::Hs)
+        .sub(3, 4, 3)
+        .sub_c(3, 3, 1)
+        .ret_r(3)
+        .build()
+}
+
+/// MOV r1, 47
+/// MOV r2, 5
+/// UDIV r3, r1, r2   <-- 9
+/// UREM r4, r1, r2   <-- 2
+/// MUL r3, r3, r2
+/// ADD r3, r3, r4
+/// SUB r3, r3, r1    <-- 0
+/// MOV r2, 0
+/// UDIV r4, r1, r2   <-- 0
+/// UREM r2, r1, r2   <-- 47
+/// SUB r2, r2, r1
+/// ADD r3, r3, r4
+/// ADD r3, r3, r2
+/// RET r3
+#[cfg(test)]
+pub fn udiv_urem() -> Prog {
+    Builder::new()
+        .mov_c(1, 47)
+        .mov_c(2, 5)
+        .udiv(3, 1, 2)
+        .urem(4, 1, 2)
+        .mul(3, 3, 2)
+        .add(3, 3, 4)
+        .sub(3, 3, 1)
+        .mov_c(2, 0)
+        .udiv(4, 1, 2)
+        .urem(2, 1, 2)
+        .sub(2, 2, 1)
+        .add(3, 3, 4)
+        .add(3, 3, 2)
+        .ret_r(3)
+        .build()
+}
+
+/// Sums the words 3, 5, 7 of the data image at address 100
+///
+/// MOV r1, 100
+/// LDR r2, r1
+/// ADD r1, r1, 1
+/// LDR r3, r1
+/// ADD r2, r2, r3
+/// ADD r1, r1, 1
+/// LDR r3, r1
+/// ADD r2, r2, r3
+/// SUB r2, r2, 15
+/// RET r2
+#[cfg(test)]
+pub fn ldr_data() -> Prog {
+    Builder::new()
+        .data(100, &[3, 5, 7])
+        .mov_c(1, 100)
+        .ldr(2, 1)
+        .add_c(1, 1, 1)
+        .ldr(3, 1)
+        .add(2, 2, 3)
+        .add_c(1, 1, 1)
+        .ldr(3, 1)
+        .add(2, 2, 3)
+        .sub_c(2, 2, 15)
+        .ret_r(2)
+        .build()
+}
+
+/// Sums the constants 3, 5 and 7 of a read-only region, stores the
+/// sum at adr and returns it minus 15. Only the words at 0, ..., 15
+/// are writable.
+///
+/// MOV r1, 100
+/// LDR r2, r1
+/// ADD r1, r1, 1
+/// LDR r3, r1
+/// ADD r2, r2, r3
+/// ADD r1, r1, 1
+/// LDR r3, r1
+/// ADD r2, r2, r3
+/// MOV r4, adr
+/// STR r4, r2
+/// LDR r3, r4
+/// SUB r3, r3, 15
+/// RET r3
+#[cfg(test)]
+pub fn str_regions(adr: Word) -> Prog {
+    use crate::miniram::lang::Perm;
+    Builder::new()
+        .data(100, &[3, 5, 7])
+        .region(100, 103, Perm::Read)
+        .region(0, 16, Perm::ReadWrite)
+        .mov_c(1, 100)
+        .ldr(2, 1)
+        .add_c(1, 1, 1)
+        .ldr(3, 1)
+        .add(2, 2, 3)
+        .add_c(1, 1, 1)
+        .ldr(3, 1)
+        .add(2, 2, 3)
+        .mov_c(4, adr)
+        .strr(4, 2)
+        .ldr(3, 4)
+        .sub_c(3, 3, 15)
+        .ret_r(3)
+        .build()
+}
+
+/// Stores 0 to the high word of the first instruction, which is in
+/// memory at ADR_CODE in the Von Neumann mode, and returns 0
+///
+/// MOV r1, ADR_CODE
+/// MOV r2, 0
+/// STR r1, r2
+/// RET r2
+#[cfg(test)]
+pub fn str_code() -> Prog {
+    use crate::miniram::lang::ADR_CODE;
+    Builder::new()
+        .mov_c(1, ADR_CODE)
+        .mov_c(2, 0)
+        .strr(1, 2)
+        .ret_r(2)
+        .build()
+}
+
+/// Outputs the Fibonacci numbers 2, 3, 5, 8, 13 and returns 5
+///
+/// MOV r1, 1
+/// MOV r2, 1
+/// MOV r3, 0
+/// ADD r1, r1, r2   <-- loop
+/// SUB r2, r1, r2
+/// OUT r1
+/// ADD r3, r3, 1
+/// CMP r3, 5
+/// B.NE loop
+/// RET r3
+#[cfg(test)]
+pub fn out_fib() -> Prog {
+    Builder::new()
+        .mov_c(1, 1)
+        .mov_c(2, 1)
+        .mov_c(3, 0)
+        .add(1, 1, 2)
+        .sub(2, 1, 2)
+        .out(1)
+        .add_c(3, 3, 1)
+        .cmp_c(3, 5)
+        .b_cond_rel(CondCode::Ne, -5)
+        .ret_r(3)
+        .build()
+}
+
+#[cfg(test)]
+pub fn orr_mvn_shl() -> Prog {
+    Builder::new()
+        .mov_c(1, 0xF0)
+        .mov_c(2, 0x0F)
+        .or(3, 1, 2)
+        .not(4, 3)
+        .shl(5, 8, 3)
+        .and(6, 4, 5)
+        .mov_c(1, 0xFF00)
+        .mov_c(2, 14)
+        .cmp(6, 1)
+        .b_cond(CondCode::Ne, 2)
+        .not(4, 4)
+        .cmp(4, 3)
+        .b_cond(CondCode::Ne, 2)
+        .ret_c(0)
+        .ret_c(1)
+        .build()
+}
+
+#[cfg(test)]
+pub fn shift_r() -> Prog {
+    Builder::new()
+        .mov_c(1, 0x80000001)
+        .mov_c(2, 36) // shift by 36 mod 32 = 4
+        .mov_c(6, 14)
+        .shr_r(3, 2, 1)
+        .shl_r(4, 2, 1)
+        .rotr_r(5, 2, 1)
+        .or(3, 3, 4)
+        .mov_c(4, 0x08000010)
+        .cmp(3, 4)
+        .b_cond(CondCode::Ne, 6)
+        .mov_c(4, 0x18000000)
+        .cmp(5, 4)
+        .b_cond(CondCode::Ne, 6)
+        .ret_c(0)
+        .ret_c(1)
+        .build()
+}
+
+#[cfg(test)]
+pub fn alu_c() -> Prog {
+    Builder::new()
+        .mov_c(6, 12)
+        .mov_c(1, 5)
+        .add_c(2, 1, 0xFFFFFFFF)
+        .and_c(4, 1, 4)
+        .xor_c(5, 4, 6)
+        .sub_c(3, 2, 4)
+        .b_cond(CondCode::Ne, 6)
+        .cmp_c(5, 2)
+        .b_cond(CondCode::Ne, 6)
+        .cmp_c(4, 5)
+        .b_cond(CondCode::Hs, 6)
+        .ret_c(0)
+        .ret_c(1)
+        .build()
+}
+
+#[cfg(test)]
+pub fn b_imm() -> Prog {
+    Builder::new()
+        .mov_c(1, 3)
+        .b_rel(3)
+        .ret_c(1)
+        .ret_c(1)
+        // loop: decrement r1 until it is zero
+        .sub_c(1, 1, 1)
+        .b_cond_rel(CondCode::Ne, -1)
+        .cmp_c(1, 0)
+        .b_cond_c(CondCode::Ne, 2)
+        .b_c(10)
+        .ret_c(1)
+        .cmp_c(1, 0)
+        .b_cond_c(CondCode::Eq, 13)
+        .ret_c(1)
+        .ret_c(0)
+        .build()
+}
+
+#[cfg(test)]
+pub fn bl() -> Prog {
+    Builder::new()
+        .bl_c(8)
+        .mov_c(1, 3)
+        .mov_c(4, 10)
+        .bl(4)
+        .add(2, 2, 3)
+        .cmp_c(2, 16)
+        .b_cond_c(CondCode::Eq, 12)
+        .ret_c(1)
+        // subroutine: r3 = 7
+        .mov_c(3, 7)
+        .ret_lr()
+        // subroutine: r2 = r1 * r1
+        .mul(2, 1, 1)
+        .ret_lr()
+        .ret_c(0)
+        .build()
+}
+
+/// Calls the subroutine at the address in lr, which BL replaces by
+/// the return address
+///
+/// MOV lr, 4
+/// BL lr        <-- jumps to 4, and sets lr = 2
+/// RET 1
+/// RET 1
+/// CMP lr, 2
+/// B.NE 3
+/// RET 0
+#[cfg(test)]
+pub fn bl_lr() -> Prog {
+    Builder::new()
+        .mov_c(LR, 4)
+        .bl(LR)
+        .ret_c(1)
+        .ret_c(1)
+        .cmp_c(LR, 2)
+        .b_cond_c(CondCode::Ne, 3)
+        .ret_c(0)
+        .build()
+}
+
+/// Expects 0x44332211 at address 0
+#[cfg(test)]
+pub fn ldrb_strb() -> Prog {
+    Builder::new()
+        .mov_c(1, 1)
+        .ldrb(2, 1)
+        .mov_c(3, 0x12AB)
+        .mov_c(1, 6)
+        .strb(1, 3)
+        .mov_c(1, 2)
+        .strh(1, 3)
+        .mov_c(1, 0)
+        .ldr(4, 1)
+        .mov_c(1, 1)
+        .ldr(5, 1)
+        .mov_c(1, 6)
+        .ldrh(6, 1)
+        .add(2, 2, 6)
+        .cmp_c(2, 0xCD)
+        .b_cond_l(CondCode::Ne, "fail")
+        .cmp_c(4, 0x12AB2211)
+        .b_cond_l(CondCode::Ne, "fail")
+        .cmp_c(5, 0x00AB0000)
+        .b_cond_l(CondCode::Ne, "fail")
+        .ret_c(0)
+        .label("fail")
+        .ret_c(1)
+        .build()
+}
+
+/// Returns 0 if the argument at address 0 has as many bits set as
+/// the argument at address 1
+#[cfg(test)]
+pub fn popcount_eq() -> Prog {
+    Builder::new()
+        .mov_c(1, 0)
+        .ldr(1, 1)
+        .mov_c(2, 0)
+        // r2 = number of bits set in r1
+        .while_nz(1, |b| b.and_c(3, 1, 1).add(2, 2, 3).shr(1, 1, 1))
+        .mov_c(3, 1)
+        .ldr(3, 3)
+        .sub(2, 2, 3)
+        .if_z(2, |b| b.ret_c(0))
+        .ret_c(1)
+        .build()
+}
+
+/// Returns 0 if the first value of the private tape is the integer
+/// square root of the argument at address 0.
+#[cfg(test)]
+pub fn isqrt_hint() -> Prog {
+    Builder::new()
+        .mov_c(1, 0)
+        .ldr(1, 1)
+        .hint(2)
+        .mul(3, 2, 2)
+        .cmp(1, 3)
+        .b_cond_l(CondCode::Lo, "fail")
+        .add_c(4, 2, 1)
+        .mul(4, 4, 4)
+        .cmp(1, 4)
+        .b_cond_l(CondCode::Hs, "fail")
+        .ret_c(0)
+        .label("fail")
+        .ret_c(1)
+        .build()
+}
+
+/// Returns 0 only if the value read from the private tape is the sum
+/// of the two values read from the public tape
+#[cfg(test)]
+pub fn read_public() -> Prog {
+    Builder::new()
+        .read(1, Tape::Public)
+        .read(2, Tape::Public)
+        .hint(3)
+        .add(4, 1, 2)
+        .cmp(4, 3)
+        .b_cond_c(CondCode::Ne, 7)
+        .ret_c(0)
+        .ret_c(1)
+        .build()
+}
+
 #[cfg(test)]
 pub fn ldr_2_args() -> Prog {
     let x = R1;
@@ -364,7 +835,7 @@ pub fn shr_5_1654560() -> Prog {
 pub fn rotr_8_0xb301() -> Prog {
     assert_eq!(0xb301u32.rotate_right(8), 0x10000b3);
     Builder::new()
-        .mov_c(1, 0xb301u32)
+        .mov_c(1, 0xb301)
         .mov_c(2, 0x10000b3)
         .rotr(1, 8, 1)
         .sub(3, 2, 1)
@@ -372,34 +843,93 @@ pub fn rotr_8_0xb301() -> Prog {
         .build()
 }
 
+/// MOV r1, 0xFFFFFFFF
+/// MOV r2, 3
+/// MUL r3, r1, r2
+/// MOV r4, 0xFFFFFFFD
+/// SUB r3, r3, r4
+/// RET r3
+#[cfg(test)]
+pub fn mul_0xffffffff_3() -> Prog {
+    Builder::new()
+        .mov_c(1, 0xFFFFFFFF)
+        .mov_c(2, 3)
+        .mul(3, 1, 2)
+        .mov_c(4, 0xFFFFFFFD)
+        .sub(3, 3, 4)
+        .ret_r(3)
+        .build()
+}
+
+/// MOV r1, 0xFFFFFFFF
+/// MOV r2, 3
+/// UMULH r3, r1, r2
+/// MOV r4, 2
+/// SUB r3, r3, r4
+/// RET r3
+#[cfg(test)]
+pub fn umulh_0xffffffff_3() -> Prog {
+    Builder::new()
+        .mov_c(1, 0xFFFFFFFF)
+        .mov_c(2, 3)
+        .umulh(3, 1, 2)
+        .mov_c(4, 2)
+        .sub(3, 3, 4)
+        .ret_r(3)
+        .build()
+}
+
+/// MOV r1, 0x10000
+/// MOV r3, 5
+/// MUL r2, r1, r1  <-- sets Z, as 2^16 * 2^16 = 2^32
+/// B Z r3          <-- skips next instr
+/// RET 1
+/// RET r2
+#[cfg(test)]
+pub fn mul_z() -> Prog {
+    Builder::new()
+        .mov_c(1, 0x10000)
+        .mov_c(3, 5)
+        .mul(2, 1, 1)
+        .b_z(3)
+        .ret_c(1)
+        .ret_r(2)
+        .build()
+}
+
+// The SHA-256 programs below assume 32 bit words
+
 // 536870776 = 2^29 - 2^3 - 2^7
 // hashes: 8 words
+#[cfg(not(feature = "word64"))]
 const ADR_H: u32 = 536870776;
 // consts: 64 words
+#[cfg(not(feature = "word64"))]
 const ADR_K: u32 = 536870776 + 8;
 // working vars: 64 words
+#[cfg(not(feature = "word64"))]
 const ADR_W: u32 = 536870776 + 8 + 64;
 
 /// Returns a program which takes an input x and verifies that
 /// compressing x with SHA256 (as described in FIPS 180-4) yields
-/// the (hardcoded) mac.
-pub fn verify_compress(mac: [u32; 16], n_blocks: u32) -> Prog {
+/// the mac, read from the public tape.
+#[cfg(not(feature = "word64"))]
+pub fn verify_compress(n_blocks: u32) -> Prog {
     let mut b = build_compress(false, n_blocks);
 
     for i in 0..8 {
         b = b
-            .mov_c(4, 0) // Register 4 holds the result
             .mov_c(1, ADR_H + i)
             .ldr(2, 1)
-            .mov_c(3, mac[i as usize])
-            .sub(2, 2, 3)
-            .or(4, 4, 2, 6)
+            .read(3, Tape::Public)
+            .cmp(2, 3)
+            .b_cond_l(CondCode::Ne, "fail")
     }
-    // Register 4 is 0 only if sha256(input)=mac
-    // Add 1 to the result
-    b.ret_r(4).build()
+    // Only reached if sha256(input)=mac
+    b.ret_c(0).label("fail").ret_c(1).build()
 }
 
+#[cfg(not(feature = "word64"))]
 pub fn compress(verbose: bool, n_blocks: u32) -> Prog {
     let b = build_compress(verbose, n_blocks);
     b.ret_c(0).build()
@@ -409,6 +939,7 @@ pub fn compress(verbose: bool, n_blocks: u32) -> Prog {
 /// with SHA256.
 ///
 /// x is assumed to be padded (as described in FIPS 180-4).
+#[cfg(not(feature = "word64"))]
 pub fn build_compress(verbose: bool, n_blocks: u32) -> Builder {
     let mut b_ = Builder::new();
     // registers of local vars
@@ -427,16 +958,14 @@ pub fn build_compress(verbose: bool, n_blocks: u32) -> Builder {
     let adr_m = 5;
     b_ = b_.mov_c(adr_m, 0);
 
-    // initialize consts
-    b_ = add_sha256_consts(b_, ADR_H, ADR_K);
+    // initial hash values and round constants
+    b_ = b_.data(ADR_H, &sha256::H32).data(ADR_K, &sha256::K32);
 
-    // loop start:
-    let loop_head = 0x0000d9u32;
+    b_ = b_.label("loop");
 
     // 1. Prepare message schedule W:
     // Uses r1, r2, r3 and r4 as scratch registers
     for t in 0..64u32 {
-        b_ = b_.mov_c(3, 1); // const 1 in scratch register
         if t < 16 {
             // Wt = Mt
             b_ = b_
@@ -444,7 +973,7 @@ pub fn build_compress(verbose: bool, n_blocks: u32) -> Builder {
                 .ldr(2, 1)
                 .mov_c(1, ADR_W + t)
                 .strr(1, 2)
-                .add(adr_m, adr_m, 3) // adr_m += 1
+                .add_c(adr_m, adr_m, 1)
         } else {
             // Wt = s1(Wt-2) + Wt-7 + s0(Wt-15) + Wt-16
             b_ = b_.mov_c(2, ADR_W + t - 2).ldr(2, 2);
@@ -542,19 +1071,10 @@ pub fn build_compress(verbose: bool, n_blocks: u32) -> Builder {
         .add(2, 2, h)
         .strr(1, 2);
 
-    let loop_done = 0x000f30;
-
     // check if done:
     b_ = b_
-        .mov_c(1, loop_done)
-        .mov_c(2, n_blocks)
-        .mov_r(3, adr_m)
-        .sub(2, 2, 3)
-        .b_z(1)
-        .mov_c(1, loop_head)
-        .b(1);
-
-    // loop_done <--- here
+        .cmp_c(adr_m, n_blocks)
+        .b_cond_l(CondCode::Ne, "loop");
     // b_ = b_.print(0);
 
     // 4. Print resulting hashes
@@ -587,33 +1107,9 @@ pub fn build_compress(verbose: bool, n_blocks: u32) -> Builder {
     b_
 }
 
-// Initilizes constants in mem addresses 64,..,135:
-// Uses r1 and r2 as scratch registers
-fn add_sha256_consts(mut b: Builder, mut adr_h: u32, mut adr_k: u32) -> Builder {
-    // initial hash values
-    let hs = [
-        0x6a09e667u32,
-        0xbb67ae85u32,
-        0x3c6ef372u32,
-        0xa54ff53au32,
-        0x510e527fu32,
-        0x9b05688cu32,
-        0x1f83d9abu32,
-        0x5be0cd19u32,
-    ];
-    for h in hs {
-        b = b.mov_c(1, h).mov_c(2, adr_h).strr(2, 1);
-        adr_h += 1;
-    }
-    for k in sha256::K32 {
-        b = b.mov_c(1, k).mov_c(2, adr_k).strr(2, 1);
-        adr_k += 1;
-    }
-    b
-}
-
 // Computes s0(x) = ROTR_7(x) + ROTR_18(x) + SHR_3(x)
 // Puts result in dst, uses r1 as scratch register
+#[cfg(not(feature = "word64"))]
 fn sha256_s0(b: Builder, x: Reg, dst: Reg) -> Builder {
     assert_ne!(dst, x);
     assert_ne!(x, 1);
@@ -626,6 +1122,7 @@ fn sha256_s0(b: Builder, x: Reg, dst: Reg) -> Builder {
 
 // Computes s1(x) = ROTR_17(x) + ROTR_19(x) + SHR_10(x)
 // Puts result in dst, uses r1 as scratch register
+#[cfg(not(feature = "word64"))]
 fn sha256_s1(b: Builder, x: Reg, dst: Reg) -> Builder {
     assert_ne!(dst, x);
     assert_ne!(x, 1);
@@ -638,6 +1135,7 @@ fn sha256_s1(b: Builder, x: Reg, dst: Reg) -> Builder {
 
 // Computes sigma0(x) = ROTR_2(x) + ROTR_13(x) + ROTR_22(x)
 // Uses r1, r2, r3 as caller-save registers
+#[cfg(not(feature = "word64"))]
 fn sha256_sigma0(b: Builder, x: Reg, dst: Reg) -> Builder {
     b.rotr(1, 2, x)
         .rotr(2, 13, x)
@@ -648,6 +1146,7 @@ fn sha256_sigma0(b: Builder, x: Reg, dst: Reg) -> Builder {
 
 // Computes sigma1(x) = ROTR_6(x) + ROTR_11(x) + ROTR_25(x)
 // Uses r1, r2, r3 as caller-save registers
+#[cfg(not(feature = "word64"))]
 fn sha256_sigma1(b: Builder, x: Reg, dst: Reg) -> Builder {
     b.rotr(1, 6, x)
         .rotr(2, 11, x)
@@ -658,12 +1157,14 @@ fn sha256_sigma1(b: Builder, x: Reg, dst: Reg) -> Builder {
 
 // Computes ch(x, y, z) = z + (x & (y + z))
 // Uses r1, r2, r3 as caller-save registers
+#[cfg(not(feature = "word64"))]
 fn sha256_ch(b: Builder, x: Reg, y: Reg, z: Reg, dst: Reg) -> Builder {
     b.xor(1, y, z).and(2, x, 1).xor(dst, 2, z)
 }
 
 // Computes maj(x, y, z) = (x & y) + (x & z) + (y & z)
 // Uses r1, r2, r3 as caller-save registers
+#[cfg(not(feature = "word64"))]
 fn sha256_maj(b: Builder, x: Reg, y: Reg, z: Reg, dst: Reg) -> Builder {
     b.and(1, x, y)
         .and(2, x, z)
@@ -679,14 +1180,81 @@ fn test_mul() {
     let time_bound = Some(10000);
     let p = &mul();
     let args = vec![3, 4];
-    let res = interpret(p, args, time_bound);
+    let res = interpret(p, args, vec![], vec![], time_bound);
     assert_eq!(res.unwrap().0, 12);
 
     let args = vec![132, 45];
-    let res = interpret(p, args, time_bound);
+    let res = interpret(p, args, vec![], vec![], time_bound);
     assert_eq!(res.unwrap().0, 132 * 45);
 }
 
+#[test]
+#[cfg(test)]
+fn test_mul_native_eq() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(1000);
+    let p = &mul_native_eq();
+    let args = vec![31, 65, 31 * 65];
+    let res = interpret(p, args, vec![], vec![], time_bound);
+    assert_eq!(res.unwrap().0, 0);
+
+    // x * (x + 1) - x = 2^w wraps to 0
+    let x: Word = 1 << (Word::BITS / 2);
+    let args = vec![x, x + 1, x];
+    let res = interpret(p, args, vec![], vec![], time_bound);
+    assert_eq!(res.unwrap().0, 0);
+
+    let args = vec![Word::MAX, 3, Word::MAX - 2];
+    let res = interpret(p, args, vec![], vec![], time_bound);
+    assert_eq!(res.unwrap().0, 0);
+}
+
+#[test]
+#[cfg(test)]
+fn test_b_cond() {
+    use crate::miniram::interpreter::interpret;
+    use CondCode::*;
+    let time_bound = Some(10);
+    let xs: [Word; 6] = [0, 1, 2, 0x7FFFFFFF, 0x80000000, 0xFFFFFFFF];
+    for x in xs {
+        for y in xs {
+            // compare as unsigned and signed integers
+            let (sx, sy) = (x as SWord, y as SWord);
+            let sub_overflows = sx.checked_sub(sy).is_none();
+            let sub_negative = (x.wrapping_sub(y) as SWord) < 0;
+            let conds = [
+                (Eq, x == y),
+                (Ne, x != y),
+                (Hs, x >= y),
+                (Lo, x < y),
+                (Mi, sub_negative),
+                (Pl, !sub_negative),
+                (Vs, sub_overflows),
+                (Vc, !sub_overflows),
+                (Hi, x > y),
+                (Ls, x <= y),
+                (Ge, sx >= sy),
+                (Lt, sx < sy),
+                (Gt, sx > sy),
+                (Le, sx <= sy),
+            ];
+            for (cc, holds) in conds {
+                let p = &sub_b_cond(cc, x, y);
+                let res = interpret(p, vec![], vec![], vec![], time_bound).unwrap().0;
+                assert_eq!(res == 0, holds, "{x} {cc:?} {y}");
+            }
+
+            let (_, carry) = x.overflowing_add(y);
+            let add_overflows = sx.checked_add(sy).is_none();
+            for (cc, holds) in [(Hs, carry), (Vs, add_overflows)] {
+                let p = &add_b_cond(cc, x, y);
+                let res = interpret(p, vec![], vec![], vec![], time_bound).unwrap().0;
+                assert_eq!(res == 0, holds, "{x} + {y} {cc:?}");
+            }
+        }
+    }
+}
+
 #[test]
 #[cfg(test)]
 fn test_mul_eq() {
@@ -694,10 +1262,196 @@ fn test_mul_eq() {
     let time_bound = Some(1000);
     let p = &mul_eq();
     let args = vec![3, 4, 12];
-    let res = interpret(p, args, time_bound);
+    let res = interpret(p, args, vec![], vec![], time_bound);
     assert_eq!(res.unwrap().0, 0);
 
     let args = vec![31, 65, 31 * 65];
-    let res = interpret(p, args, time_bound);
+    let res = interpret(p, args, vec![], vec![], time_bound);
     assert_eq!(res.unwrap().0, 0);
 }
+
+#[test]
+#[cfg(test)]
+fn test_isqrt_hint() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(20);
+    let p = &isqrt_hint();
+    for (x, s, ok) in [(17, 4, true), (16, 4, true), (15, 4, false), (25, 4, false)] {
+        let res = interpret(p, vec![x], vec![], vec![s], time_bound)
+            .unwrap()
+            .0;
+        assert_eq!(res == 0, ok, "isqrt({x}) = {s}");
+    }
+    assert!(interpret(p, vec![17], vec![], vec![], time_bound).is_err());
+}
+
+#[test]
+#[cfg(test)]
+fn test_out_fib() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(34);
+    let p = &out_fib();
+    let (res, outputs, _) = interpret(p, vec![], vec![], vec![], time_bound).unwrap();
+    assert_eq!(res, 5);
+    assert_eq!(outputs, vec![2, 3, 5, 8, 13]);
+}
+
+#[test]
+#[cfg(test)]
+fn test_ldr_data() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(10);
+    let p = &ldr_data();
+    let res = interpret(p, vec![], vec![], vec![], time_bound);
+    assert_eq!(res.unwrap().0, 0);
+}
+
+#[test]
+#[cfg(test)]
+fn test_str_regions() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(13);
+    for (adr, res) in [
+        (8, Ok(0)),
+        (101, Err("writing to read-only memory")),
+        (50, Err("memory access outside the regions")),
+    ] {
+        let p = &str_regions(adr);
+        let res_ = interpret(p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+        assert_eq!(res_, res, "STR at {adr}");
+    }
+
+    // Without regions, only the highest address is out of range
+    let mut p = str_regions(101);
+    p.regions.clear();
+    assert!(interpret(&p, vec![], vec![], vec![], time_bound).is_ok());
+    let p = &Builder::new()
+        .mov_c(1, 0)
+        .not(2, 1)
+        .strr(2, 1)
+        .ret_c(0)
+        .build();
+    let res = interpret(p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, Err("address out of range"));
+}
+
+#[test]
+#[cfg(test)]
+fn test_popcount_eq() {
+    use crate::miniram::interpreter::interpret;
+    let p = &popcount_eq();
+    for (x, y, res) in [(0, 0, 0), (0b1011, 3, 0), (0xFFFF_FFFF, 32, 0), (6, 1, 1)] {
+        let res_ = interpret(p, vec![x, y], vec![], vec![], Some(1000));
+        assert_eq!(res_.unwrap().0, res);
+    }
+}
+
+#[test]
+#[cfg(test)]
+fn test_data_overlap() {
+    use crate::miniram::interpreter::{interpret, interpret_von_neumann};
+    use crate::miniram::lang::ADR_CODE;
+    let time_bound = Some(10);
+    let err = Err("data image overlaps the arguments, the program or itself");
+    let p = &ldr_data();
+    let res = interpret(p, vec![0; 100], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, Ok(0));
+    let res = interpret(p, vec![0; 101], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, err);
+
+    let mut p = ldr_data();
+    p.data.push((102, 7));
+    let res = interpret(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, err);
+
+    // The program is only in memory in the Von Neumann mode
+    let mut p = ldr_data();
+    p.data.push((ADR_CODE, 0));
+    let res = interpret(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, Ok(0));
+    let res = interpret_von_neumann(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, err);
+}
+
+#[test]
+#[cfg(test)]
+fn test_data_max() {
+    use crate::miniram::interpreter::interpret;
+    use crate::miniram::lang::{Perm, Region};
+    let time_bound = Some(10);
+    let err = Err("data image or regions reach the highest address");
+    let mut p = ldr_data();
+    p.data.push((Word::MAX, 0));
+    let res = interpret(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, err);
+
+    let mut p = ldr_data();
+    let (start, end, perm) = (0, Word::MAX, Perm::ReadWrite);
+    p.regions.push(Region { start, end, perm });
+    let res = interpret(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, err);
+    p.regions[0].end -= 1;
+    let res = interpret(&p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+    assert_eq!(res, Ok(0));
+}
+
+#[test]
+#[cfg(test)]
+fn test_ldr_uninitialized() {
+    use crate::miniram::interpreter::interpret;
+    let time_bound = Some(10);
+    let ldr = |b: Builder| b.mov_c(1, 50).ldr(2, 1).ret_r(2);
+    let ldrb = |b: Builder| b.mov_c(1, 200).ldrb(2, 1).ret_r(2);
+    let ldrh = |b: Builder| b.mov_c(1, 200).ldrh(2, 1).ret_r(2);
+    for load in [ldr, ldrb, ldrh] {
+        let p = &load(Builder::new()).build();
+        let res = interpret(p, vec![], vec![], vec![], time_bound).map(|r| r.0);
+        assert_eq!(res, Err("reading uninitialized memory"));
+    }
+}
+
+#[test]
+#[cfg(feature = "word64")]
+fn test_word64() {
+    use crate::miniram::interpreter::interpret;
+    // Applies op to the arguments x and y in r1 and r3, and returns
+    // the result in r2
+    type Op = fn(Builder) -> Builder;
+    let run = |op: Op, x: Word, y: Word| {
+        let b = Builder::new().mov_c(1, 0).ldr(1, 1).mov_c(3, 1).ldr(3, 3);
+        let p = &op(b).ret_r(2).build();
+        let res = interpret(p, vec![x, y], vec![], vec![], Some(10));
+        res.unwrap().0
+    };
+    let add: Op = |b| b.add(2, 1, 3);
+    let sub: Op = |b| b.sub(2, 1, 3);
+    let mul: Op = |b| b.mul(2, 1, 3);
+    let umulh: Op = |b| b.umulh(2, 1, 3);
+    let shl: Op = |b| b.shl(2, 40, 1);
+    let shr: Op = |b| b.shr(2, 63, 1);
+    let rotr: Op = |b| b.rotr(2, 48, 1);
+    let shl_r: Op = |b| b.shl_r(2, 3, 1);
+    let shr_r: Op = |b| b.shr_r(2, 3, 1);
+    let rotr_r: Op = |b| b.rotr_r(2, 3, 1);
+    for (op, x, y, res) in [
+        // Words wrap at 2^64, not 2^32
+        (add, 0xFFFF_FFFF, 1, 1 << 32),
+        (add, Word::MAX, 1, 0),
+        (sub, 0, 1, Word::MAX),
+        (mul, 1 << 32, 3, 3 << 32),
+        (mul, 1 << 32, 1 << 32, 0),
+        (umulh, 1 << 63, 4, 2),
+        // Shift amounts from 32 to 63
+        (shl, 1, 0, 1 << 40),
+        (shr, 1 << 63, 0, 1),
+        (rotr, 1, 0, 1 << 16),
+        (shl_r, 1, 33, 1 << 33),
+        (shr_r, 1 << 63, 63, 1),
+        (rotr_r, 1, 32, 1 << 32),
+        // Register amounts are taken mod 64
+        (shl_r, 3, 64, 3),
+        (shr_r, 1 << 40, 72, 1 << 32),
+    ] {
+        assert_eq!(run(op, x, y), res, "{x:#x}, {y:#x}");
+    }
+}
@@ -3,27 +3,69 @@ use crate::miniram::lang::reg::*;
 use crate::miniram::lang::*;
 
 use backend::ProofCtx;
-use utils::{permutation, waksman};
+use utils::{permutation, sha256, waksman};
 
 use utils::circuit::{
     builder::{self, Builder},
-    gadgets, ARG0, ARG1,
+    gadgets::{self, HiInstr},
+    ARG0, ARG1,
 };
 
-use super::encode::encode;
+use super::encode::{encode, EProg};
 
 type Witness = Vec<Word>;
 
-/// Encodes args as a witness for the correct execution of the
-/// MiniRAM program prog (i.e a 0 evaluation).
+/// Where the program is stored when proving its execution.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Arch {
+    /// The program is hard-coded into the circuit as constants, and
+    /// instructions are fetched by selecting the constants at pc.
+    Harvard,
+    /// The program is loaded into memory at ADR_CODE, and
+    /// instructions are fetched by reads that are checked by the
+    /// memory consistency circuit.
+    VonNeumann,
+    /// Like VonNeumann, but the program is part of the witness, and
+    /// only its length and SHA-256 digest (see program_digest) is
+    /// known to the verifier.
+    Private,
+}
+
+/// Returns the digest of prog that is checked by the circuit in the
+/// Private mode, i.e the SHA-256 digest of the words of the encoded
+/// program.
+pub fn program_digest(prog: &Prog) -> [Word; 8] {
+    sha256::digest(&code_words(prog))
+}
+
+/// Encodes args and the private tape as a witness for the correct
+/// execution of the MiniRAM program prog.
 ///
 /// The witness consists of the local state of program execution,
-/// i.e a Vec<LocalState> that is as long as the time bound t
-pub fn encode_witness(prog: &Prog, args: Vec<Word>, t: usize, ctx: &mut ProofCtx) -> Res<Witness> {
+/// i.e a Vec<LocalState> that is as long as the time bound t. The
+/// values read from the private tape are part of the witness as the
+/// value of the destination register of READ instructions.
+///
+/// Returns the witness and the public outputs of the proof, i.e the
+/// value returned by prog followed by the values output by OUT
+/// instructions (in the order opened by the circuit).
+pub fn encode_witness(
+    prog: &Prog,
+    args: Vec<Word>,
+    public: Vec<Word>,
+    private: Vec<Word>,
+    t: usize,
+    arch: Arch,
+    ctx: &mut ProofCtx,
+) -> Res<(Witness, Vec<Word>)> {
     ctx.start_time("interpret program");
-    let (res, mut lsts) = interpret(prog, args, Some(t))?;
+    let (res, outputs, mut lsts) = match arch {
+        Arch::Harvard => interpret(prog, args, public, private, Some(t))?,
+        Arch::VonNeumann | Arch::Private => {
+            interpret_von_neumann(prog, args, public, private, Some(t))?
+        }
+    };
     ctx.stop_time();
-    assert_eq!(res, 0);
     if lsts.len() < t {
         // assume program runs for at least one step
         let last_st = *lsts.last().unwrap();
@@ -31,21 +73,51 @@ pub fn encode_witness(prog: &Prog, args: Vec<Word>, t: usize, ctx: &mut ProofCtx
             lsts.push(last_st);
         }
     }
-    Ok(convert_localstates(lsts, ctx))
+    let code = match arch {
+        Arch::Harvard => None,
+        Arch::VonNeumann | Arch::Private => Some(code_words(prog)),
+    };
+    let n_reg = n_reg(prog);
+    let private = arch == Arch::Private;
+    let w = convert_localstates(lsts, n_reg, &outputs, &prog.data, code, private, ctx);
+    Ok((w, [vec![res], outputs].concat()))
 }
 
 /// Convert the local states to the witness, which is a vector W of
 /// values from the circuit layed out as
 ///
-///   W = S1, S2, ..., St, c1, ..., ck
+///   W = S1, S2, ..., St, m1, ..., mt, d1, ..., dt, o1, ..., on, c1, ..., ck
+///
+/// or, if code is the words of the program loaded into memory (in
+/// the Von Neumann mode),
+///
+///   W = S1, ..., St, m1, ..., mt, d1, ..., dt, o1, ..., on, f1, ..., ft, c1, ..., ck
+///
+/// where the words of the program p1, ..., pn are added before c1 if
+/// private is set (in the Private mode),
 ///
 /// where Si represents the i'th local state with the value of the CPU
 ///
-///   Si = pc, r1, ..., r15, Z
+///   Si = pc, r1, ..., r(n-1), Z, C, N, V, h, o
 ///
-/// and where c1, ..., ck is the configuration of the AS-Waksman network
-/// that sorts S1, S2, ..., St according to memory accesses with
-/// ties broken by timestamp.
+/// where n is the register count n_reg of the program, h is the
+/// number of values read from the public tape and o is the number
+/// of values output,
+/// and where mi is the value of the memory word accessed by the i'th
+/// instruction before the access (or 0, if the instruction is not a
+/// memory operation), and where di is the remainder of the i'th
+/// instruction if it is UDIV, its quotient if it is UREM, and
+/// otherwise 0, and where o1, ..., on are the values output by OUT
+/// instructions, and where fi is the high and low word of the
+/// i'th instruction fetched from memory, and where c1, ..., ck is
+/// the configuration of the AS-Waksman network that sorts the memory
+/// trace according to memory accesses with ties broken by
+/// timestamp.
+///
+/// The memory trace is the writes of the data image followed by S1,
+/// S2, ..., St, or in the Von Neumann mode the writes of the data
+/// image and the code words followed by the two reads of fi and Si
+/// for each i.
 ///
 /// TODO: fix docs of:
 ///
@@ -62,23 +134,88 @@ pub fn encode_witness(prog: &Prog, args: Vec<Word>, t: usize, ctx: &mut ProofCtx
 ///   - val_i     is the value read/ written
 ///
 ///   - is_load_i is 1 only if the operation was a LDR
-fn convert_localstates(lsts: Vec<LocalStateAug>, ctx: &mut ProofCtx) -> Witness {
+fn convert_localstates(
+    lsts: Vec<LocalStateAug>,
+    n_reg: usize,
+    outputs: &[Word],
+    data: &[(Word, Word)],
+    code: Option<Vec<Word>>,
+    private: bool,
+    ctx: &mut ProofCtx,
+) -> Witness {
     let mut res = vec![];
     // Push S1, S2, ..., St
     for s in lsts.iter() {
-        for val in s.st.0 {
+        for val in &s.st.0[..n_reg] {
             // Push value of registers
-            res.push(val)
+            res.push(*val)
         }
         for flag in s.st.1 {
             // Push value of conditional flags
             res.push(Word::from(flag))
         }
+        // Push position of public tape and number of values output
+        res.push(s.st.2);
+        res.push(s.st.3)
+    }
+    // Push m1, m2, ..., mt
+    for s in lsts.iter() {
+        res.push(match s.ma {
+            MemAccess::None => 0,
+            MemAccess::Read { val, .. } => val,
+            MemAccess::Write { old, .. } => old,
+        })
+    }
+    // Push d1, d2, ..., dt
+    for s in lsts.iter() {
+        res.push(s.aux)
+    }
+    // Push o1, o2, ..., on
+    res.extend(outputs);
+    // The data image is written to memory before the first step
+    let mut trace = vec![];
+    for (addr, v) in data {
+        let ma = MemAccess::Write {
+            addr: *addr,
+            old: *v,
+            val: *v,
+        };
+        trace.push(LocalStateAug { ma, ..lsts[0] })
     }
-    // Compute the permutation that sorts the states according to
-    // memory accesses.
+    match code {
+        None => trace.extend(lsts),
+        Some(code) => {
+            for (k, v) in code.iter().enumerate() {
+                let ma = MemAccess::Write {
+                    addr: ADR_CODE + Word::try_from(k).unwrap(),
+                    old: *v,
+                    val: *v,
+                };
+                trace.push(LocalStateAug { ma, ..lsts[0] })
+            }
+            // The i'th instruction is fetched at the pc of S(i-1)
+            let pcs = core::iter::once(0).chain(lsts.iter().map(|s| s.st.0[usize::from(PC)]));
+            for (s, pc) in lsts.iter().zip(pcs) {
+                // Push fi
+                for k in 0..2 {
+                    let addr = ADR_CODE + 2 * pc + k;
+                    let val = code[usize::try_from(2 * pc + k).unwrap()];
+                    res.push(val);
+                    let ma = MemAccess::Read { addr, val };
+                    trace.push(LocalStateAug { ma, ..*s })
+                }
+                trace.push(*s)
+            }
+            if private {
+                // Push p1, p2, ..., pn
+                res.extend(code)
+            }
+        }
+    }
+    // Compute the permutation that sorts the memory trace according
+    // to memory accesses.
     ctx.start_time("compute sorting permutation");
-    let p = permutation::sort(&lsts);
+    let p = permutation::sort(&trace);
     ctx.stop_time();
 
     // Push configuration of permutation network
@@ -96,73 +233,342 @@ fn convert_localstates(lsts: Vec<LocalStateAug>, ctx: &mut ProofCtx) -> Witness
 }
 
 /// Number of circuit elements (u32) in one LocalState of the trace
-const SIZE_LOCAL_ST: usize = N_REG + N_CFL;
+/// with n_reg registers
+fn size_local_st(n_reg: usize) -> usize {
+    n_reg + N_CFL + 2
+}
+
+/// Offset of the conditional flags, the position of the public tape
+/// and the number of values output in a LocalState of the trace with
+/// n_reg registers
+fn offset_cfl(n_reg: usize) -> usize {
+    n_reg
+}
+fn offset_head(n_reg: usize) -> usize {
+    n_reg + N_CFL
+}
+fn offset_out(n_reg: usize) -> usize {
+    n_reg + N_CFL + 1
+}
+
+/// Number of (least significant) bits of the register fields of an
+/// instruction with n_reg registers
+fn reg_bits(n_reg: usize) -> usize {
+    usize::try_from(n_reg.ilog2()).unwrap()
+}
+
+/// Parameters shared by the transition circuits of all steps
+#[derive(Clone, Copy)]
+struct TransParams<'a> {
+    /// Number of registers
+    n_reg: usize,
+    /// Range of constants holding the public tape
+    tape: (usize, usize),
+    /// Range of ids of the witness values output
+    outs: (usize, usize),
+    /// Memory regions of the program
+    regions: &'a [Region],
+    /// Whether the program is in memory at ADR_CODE (in the Von
+    /// Neumann and Private mode)
+    code: bool,
+}
 
 /// Generates a circuit for verifying the existence of an input
-/// (witness), that will make the program return 0 within time bound
-/// t.
+/// (witness), that will make the program return within time bound
+/// t, and output n_out values, when reading the values of public
+/// from the public tape.
 ///
-/// todo: Currently the program is hardcoded into the circuit as a
-/// constant. If time permits, it would be nice to change this to a
-/// Von Neumann type architecture.
-pub fn generate_circuit(prog: &Prog, time_bound: usize) -> builder::Res<Word> {
-    let n_in = time_bound * SIZE_LOCAL_ST + waksman::conf_len(time_bound);
+/// The value returned and the values output are opened by the
+/// circuit, i.e they are revealed to the verifier as the public
+/// outputs of the proof (see encode_witness).
+///
+/// The data image of the program is written to memory before the
+/// first step, with the addresses and values as constants of the
+/// circuit.
+///
+/// In the Harvard mode the program is hard-coded into the circuit,
+/// and instructions are fetched by selecting the constants at pc. In
+/// the Von Neumann mode the program is written to memory at ADR_CODE
+/// before the first step, and each instruction is fetched by two
+/// reads that are checked by the memory consistency circuit, like
+/// any other memory access. The circuit then only depends on the
+/// length of the program, and not on the instructions, except for
+/// the constants of the initial writes. In the Private mode, the
+/// values of the initial writes are part of the witness instead,
+/// see generate_circuit_private.
+///
+/// Each local state of the trace only has the registers used by the
+/// program, see n_reg.
+pub fn generate_circuit(
+    prog: &Prog,
+    public: &[Word],
+    n_out: usize,
+    time_bound: usize,
+    arch: Arch,
+) -> builder::Res<Word> {
+    let p = encode(prog);
+    let n_reg = n_reg(prog);
+    match arch {
+        Arch::Private => {
+            let digest = &program_digest(prog);
+            let (n_instr, data, regions) = (p.len(), &prog.data, &prog.regions);
+            let io = (public, n_out);
+            generate_circuit_private(digest, n_instr, n_reg, data, regions, io, time_bound)
+        }
+        _ => build_circuit(
+            Code::Public(&p),
+            (&prog.data, &prog.regions),
+            n_reg,
+            (public, n_out),
+            time_bound,
+            arch,
+        ),
+    }
+}
+
+/// Generates the circuit of the Private mode, for verifying the
+/// existence of a program with n_instr instructions, n_reg registers,
+/// the data image data, the memory regions regions and the given
+/// digest (see program_digest), and an input, that will make the
+/// program return and output n_out values within time bound t. Input
+/// io is the public tape and n_out.
+///
+/// The program is written to memory like in the Von Neumann mode,
+/// but the values written are part of the witness, and their
+/// SHA-256 digest is checked against digest. Only the length and
+/// the register count of the program is revealed by the circuit.
+pub fn generate_circuit_private(
+    digest: &[Word; 8],
+    n_instr: usize,
+    n_reg: usize,
+    data: &[(Word, Word)],
+    regions: &[Region],
+    io: (&[Word], usize),
+    time_bound: usize,
+) -> builder::Res<Word> {
+    build_circuit(
+        Code::Private(n_instr, digest),
+        (data, regions),
+        n_reg,
+        io,
+        time_bound,
+        Arch::Private,
+    )
+}
+
+/// The program as known by the verifier
+enum Code<'a> {
+    /// The encoded program
+    Public(&'a EProg),
+    /// The number of instructions and the digest of the program
+    Private(usize, &'a [Word; 8]),
+}
+
+/// Input mem is the data image and the memory regions of the program,
+/// and io is the public tape and the number of values output
+fn build_circuit(
+    code: Code,
+    mem: (&[(Word, Word)], &[Region]),
+    n_reg: usize,
+    io: (&[Word], usize),
+    time_bound: usize,
+    arch: Arch,
+) -> builder::Res<Word> {
+    assert!(N_REGS.contains(&n_reg));
+    let (public, n_out) = io;
+    let (data, regions) = mem;
+    let size_st = size_local_st(n_reg);
+    let n_instr = match code {
+        Code::Public(p) => p.len(),
+        Code::Private(n_instr, _) => n_instr,
+    };
+
+    // number of entries in the memory trace, of witness values of
+    // the instructions fetched from memory, and of the program
+    let (n_trace, n_fetch, n_code) = match arch {
+        Arch::Harvard => (time_bound, 0, 0),
+        Arch::VonNeumann => (2 * n_instr + 3 * time_bound, 2 * time_bound, 0),
+        Arch::Private => (2 * n_instr + 3 * time_bound, 2 * time_bound, 2 * n_instr),
+    };
+    let n_trace = data.len() + n_trace;
+    let n_in = time_bound * (size_st + 2) + n_out + n_fetch + n_code + waksman::conf_len(n_trace);
+
+    // id of first memory word accessed
+    let in_mem = time_bound * size_st + ARG0;
+
+    // id of first word taken from the prover by UDIV/ UREM
+    let in_div = in_mem + time_bound;
+
+    // id of first value output
+    let in_out = in_div + time_bound;
+
+    // id of first word of instructions fetched from memory
+    let in_fetch = in_out + n_out;
+
+    // id of first word of the program
+    let in_code = in_fetch + n_fetch;
 
     // id of first permutation network config
-    let in_pconf = time_bound * SIZE_LOCAL_ST + ARG0;
+    let in_pconf = in_code + n_code;
 
     let mut b = builder::Builder::new(n_in);
     let mut outputs = vec![];
 
     // hard-code program
-    let p = encode(prog);
-    let n_instr = p.len();
-    for instr in &p {
-        let lo = *instr as u32;
-        let hi = (*instr >> 32) as u32;
-        let _ = b.push_const(hi);
-        let _ = b.push_const(lo);
-        // id of constant gate can be ignored: As the gates are the
-        // first to be added to the circuit, we just use 2i || 2i+1
-        // as the (zero indexed) instruction.
+    if let Code::Public(p) = code {
+        for instr in p {
+            let lo = *instr as u32;
+            let hi = (*instr >> 32) as u32;
+            let _ = b.push_const(hi);
+            let _ = b.push_const(lo);
+            // id of constant gate can be ignored: As the gates are
+            // the first to be added to the circuit, we just use 2i
+            // || 2i+1 as the (zero indexed) instruction.
+        }
     }
 
     // push constants
     let id_zero = b.push_const(0);
     let id_one = b.push_const(1);
-    let id_two = b.push_const(2);
     let zero = b.const_(id_zero);
     let one = b.const_(id_one);
 
-    // input of permutation networks
-    let mut perm_in_0 = vec![];
-    let mut perm_in_1 = vec![];
-    let mut perm_in_2 = vec![];
-    let mut perm_in_3 = vec![];
-    let mut ctr = zero;
+    // push public tape, preceded by a dummy value: the value read
+    // by a READ instruction is selected using the position of the
+    // tape after the read (see trans_circ)
+    let id_tape = b.push_const(0);
+    for v in public {
+        let _ = b.push_const(*v);
+    }
+    let tape = (id_tape, id_tape + public.len() + 1);
+    let outs = (in_out, in_out + n_out);
+    let params = TransParams {
+        n_reg,
+        tape,
+        outs,
+        regions,
+        code: arch != Arch::Harvard,
+    };
+
+    // memory trace of (adr, v_old, v_new), which are timestamped by
+    // their index in the trace
+    let mut trace = vec![];
+
+    // Write the data image to memory
+    for (adr, v) in data {
+        let adr = b.push_const(*adr + 1);
+        let v = b.push_const(*v);
+        let v = b.const_(v);
+        trace.push((b.const_(adr), v, v));
+    }
+
+    // In the Von Neumann and Private mode, write the program to
+    // memory, and fetch instructions from there
+    let code = if arch == Arch::Harvard {
+        None
+    } else {
+        let id_code = b.push_const(ADR_CODE + 1);
+        let id_len = b.push_const(Word::try_from(n_instr).unwrap());
+        let mut adr = b.const_(id_code);
+        let mut words = vec![];
+        for k in 0..2 * n_instr {
+            if k > 0 {
+                adr = b.add(&[adr, one]);
+            }
+            let v = match code {
+                Code::Public(_) => b.const_(ARG0 + k),
+                Code::Private(..) => in_code + k,
+            };
+            trace.push((adr, v, v));
+            words.push(v);
+        }
+        // Check the digest of the program written
+        if let Code::Private(_, digest) = code {
+            let h = gadgets::sha256(&mut b, &words, zero);
+            for (h, d) in h.into_iter().zip(digest) {
+                let d = b.push_const(*d);
+                let d = b.const_(d);
+                outputs.push(b.sub(h, d));
+            }
+        }
+        let len = b.const_(id_len);
+        Some((b.const_(id_code), b.decode32(len)))
+    };
+    // Returns the ids of the high and low word of the instruction
+    // fetched at the given step
+    let fetch =
+        |b: &mut Builder<Word>, outputs: &mut Vec<_>, trace: &mut Vec<_>, step, pc| match code {
+            None if step == 0 => (b.const_(ARG0), b.const_(ARG1)),
+            None => {
+                let pc_hi = b.add(&[pc, pc]);
+                let pc_lo = b.add(&[one, pc_hi]);
+                let instr_hi = b.select_const_range(pc_hi, ARG0, ARG0 + 2 * n_instr, 1);
+                let instr_lo = b.select_const_range(pc_lo, ARG0, ARG0 + 2 * n_instr + 1, 1);
+                (instr_hi, instr_lo)
+            }
+            Some((code, len_bits)) => {
+                let instr_hi = in_fetch + 2 * step;
+                let instr_lo = instr_hi + 1;
+                let (check, adr_hi, adr_lo) = fetch_circ(b, pc, code, len_bits, one);
+                outputs.push(check);
+                trace.push((adr_hi, instr_hi, instr_hi));
+                trace.push((adr_lo, instr_lo, instr_lo));
+                (instr_hi, instr_lo)
+            }
+        };
 
     // compose transition circuit t times, where the first
     // iteration uses initial values (zeros) for all registers
-    let (mut o, adr, v, is_load) = fst_trans_circ(&mut b, zero, one);
+    let instr = fetch(&mut b, &mut outputs, &mut trace, 0, zero);
+    let (mut o, mut is_ret, adr, v_old, v_new) =
+        fst_trans_circ(&mut b, instr, (in_mem, in_div), params, zero, one);
     outputs.append(&mut o);
-    perm_in_0.push(ctr);
-    perm_in_1.push(adr);
-    perm_in_2.push(v);
-    perm_in_3.push(is_load);
+    trace.push((adr, v_old, v_new));
 
     for step in 1..time_bound {
         // b.debug(step);
         // if step < p.len() {
         //     b.debug(p[step].try_into().unwrap());
         // }
-        let (mut o, adr, v, is_load) = trans_circ(&mut b, step - 1, n_instr, zero, one, id_two);
-        ctr = b.add(&[ctr, one]);
+        let wit = (in_mem + step, in_div + step);
+        let pc = (step - 1) * size_st + ARG0 + usize::from(PC);
+        let instr = fetch(&mut b, &mut outputs, &mut trace, step, pc);
+        let (mut o, is_ret_, adr, v_old, v_new) =
+            trans_circ(&mut b, step - 1, instr, wit, params, zero, one);
         outputs.append(&mut o);
+        trace.push((adr, v_old, v_new));
+        is_ret = is_ret_;
+    }
+
+    // The program has returned if the last instruction is RET. Open
+    // the value returned (in R1) and the values output, and check
+    // that all n_out values were output.
+    let k = (time_bound - 1) * size_st + ARG0;
+    outputs.push(b.sub(one, is_ret));
+    b.open(k + usize::from(R1));
+    let id_n_out = b.push_const(Word::try_from(n_out).unwrap());
+    let n_out = b.const_(id_n_out);
+    outputs.push(b.sub(k + offset_out(n_reg), n_out));
+    for id in outs.0..outs.1 {
+        b.open(id);
+    }
+
+    // input of permutation networks
+    let mut perm_in_0 = vec![];
+    let mut perm_in_1 = vec![];
+    let mut perm_in_2 = vec![];
+    let mut perm_in_3 = vec![];
+    let mut ctr = zero;
+    for (i, (adr, v_old, v_new)) in trace.into_iter().enumerate() {
+        if i > 0 {
+            ctr = b.add(&[ctr, one]);
+        }
         perm_in_0.push(ctr);
         perm_in_1.push(adr);
-        perm_in_2.push(v);
-        perm_in_3.push(is_load);
+        perm_in_2.push(v_old);
+        perm_in_3.push(v_new);
     }
+
     // Add the permutation networks
     let conf = &(in_pconf..n_in + ARG0).collect::<Vec<_>>();
 
@@ -171,7 +577,7 @@ pub fn generate_circuit(prog: &Prog, time_bound: usize) -> builder::Res<Word> {
     let (po2, _) = gadgets::waksman(&mut b, &perm_in_2, conf, one);
     let (po3, _) = gadgets::waksman(&mut b, &perm_in_3, conf, one);
 
-    for step in 1..time_bound {
+    for step in 1..n_trace {
         let x = (po0[step - 1], po1[step - 1], po2[step - 1], po3[step - 1]);
         let y = (po0[step], po1[step], po2[step], po3[step]);
         let mut o = mem_consistency_circ(&mut b, x, y, one);
@@ -181,9 +587,41 @@ pub fn generate_circuit(prog: &Prog, time_bound: usize) -> builder::Res<Word> {
     b.build(&outputs)
 }
 
+/// Returns (check, adr_hi, adr_lo) where check is zero only if pc is
+/// less than the length of the program, and adr_hi and adr_lo are
+/// the addresses (plus one) of the high and low word of the
+/// instruction at pc in memory.
+///
+/// Input code is the address (plus one) of the program in memory,
+/// and len_bits is the bit-decomposition of the length of the
+/// program.
+fn fetch_circ(
+    b: &mut Builder<Word>,
+    pc: usize,
+    code: usize,
+    len_bits: usize,
+    one: usize,
+) -> (usize, usize, usize) {
+    let adr_hi = b.add(&[code, pc, pc]);
+    let adr_lo = b.add(&[adr_hi, one]);
+
+    // Reads of memory that isn't written before are unconstrained,
+    // so fetching outside the program must be prevented
+    let pc_bits = b.decode32(pc);
+    let xs = &(pc_bits..pc_bits + 32).collect::<Vec<_>>();
+    let ys = &(len_bits..len_bits + 32).collect::<Vec<_>>();
+    let (lt, _) = gadgets::word_comparator(b, xs, ys, one);
+    let check = b.xor_bits(&[lt, one]);
+
+    (check, adr_hi, adr_lo)
+}
+
 /// Inputs:
-///   - x: (t, adr, v, is_load) for i'th state in the sorted trace
-///   - y: (t, adr, v, is_load) for i+1'th state in the sorted trace
+///   - x: (t, adr, v_old, v_new) for i'th state in the sorted trace
+///   - y: (t, adr, v_old, v_new) for i+1'th state in the sorted trace
+///
+/// where v_old and v_new is the value of the memory word before and
+/// after the access.
 ///
 /// Check that elements are sorted:
 ///
@@ -191,7 +629,7 @@ pub fn generate_circuit(prog: &Prog, time_bound: usize) -> builder::Res<Word> {
 ///
 /// Check memory accesses are sequentially consistent:
 ///
-///   (adr1 = adr2 AND is_load2) => v1 = v2
+///   adr1 = adr2 => v_new1 = v_old2
 ///
 /// We will allow reading arbitrary values from unitialized
 /// memory. This is also convenient for reading program arguments,
@@ -202,8 +640,8 @@ fn mem_consistency_circ(
     y: (usize, usize, usize, usize),
     one: usize,
 ) -> Vec<usize> {
-    let (t1, adr1, v1) = (x.0, x.1, x.2);
-    let (t2, adr2, v2, is_load2) = (y.0, y.1, y.2, y.3);
+    let (t1, adr1, v_new1) = (x.0, x.1, x.3);
+    let (t2, adr2, v_old2) = (y.0, y.1, y.2);
 
     // The addresses are already decoded previously in the instruction
     // fetching - don't think its possible to reuse those bits here
@@ -226,249 +664,670 @@ fn mem_consistency_circ(
     let tmp = b.or_bits(adr_lt, tmp);
     let check_sorted = b.xor_bits(&[tmp, one]);
 
-    let tmp = b.sub(v1, v2);
-    let tmp = b.mul(is_load2, tmp);
+    let tmp = b.sub(v_new1, v_old2);
     let check_mem = b.mul(adr_eq, tmp);
 
     vec![check_sorted, check_mem]
 }
 
-/// Returns (outputs, addr, val, is_load) where
+/// Returns (outputs, is_ret, addr, v_old, v_new) where
 ///
 ///   - outputs is the ids of all output nodes of the circuit
 ///
-///   - addr    is the address of the current memory instruction (or 0,
-///             if the instruction is not a LDR/ STR)
+///   - is_ret  is 1 only if the current instruction is RET
+///
+///   - addr    is the address of the current memory instruction plus
+///             one (or 0, if the instruction is not a memory operation)
+///
+///   - v_old   is the value of the memory word accessed by the
+///             current instruction before the access
 ///
-///   - val     is the value read/ written by the current memory
-///             instruction (or 0, if the instruction is not a LDR/
-///             STR)
+///   - v_new   is the value of the memory word accessed by the
+///             current instruction after the access
 ///
-///  - is_load  is 1 only if the current instruction was a LDR
+/// Input instr is the ids of the high and low word of the first
+/// instruction, wit is the ids of the witness values of the memory
+/// word accessed and of the word taken from the prover by the first
+/// instruction (see div_circ), and params holds the number of
+/// registers, the range of constants holding the public tape, the
+/// range of ids of the values output and the memory regions of the
+/// program.
 fn fst_trans_circ(
     b: &mut builder::Builder<Word>,
+    instr: (usize, usize),
+    wit: (usize, usize),
+    params: TransParams,
     zero: usize,
     one: usize,
-) -> (Vec<usize>, usize, usize, usize) {
-    // Fetch first instruction
-    let instr_hi = b.const_(ARG0);
-    let instr_lo = b.const_(ARG1);
+) -> (Vec<usize>, usize, usize, usize, usize) {
+    let TransParams {
+        n_reg,
+        tape,
+        outs,
+        regions,
+        code,
+    } = params;
+    let reg_bits = reg_bits(n_reg);
+    let (instr_hi, instr_lo) = instr;
+    let (m, d) = wit;
 
     // Decode it
-    let (op, dst, _, is_mem, is_load, is_ret, field4) = gadgets::decode_hi_instr32(b, instr_hi);
-    let (_, arg1_word) = gadgets::decode_lo_instr32(b, instr_lo);
+    let HiInstr {
+        op_bits,
+        dst,
+        is_mem,
+        is_load,
+        field4,
+        field4_bits,
+        ..
+    } = gadgets::decode_hi_instr32(b, instr_hi, reg_bits);
+    let (_, arg1_word) = gadgets::decode_lo_instr32(b, instr_lo, reg_bits);
 
     let is_str = b.xor_bits(&[is_mem, is_load]);
+    let is_bl = is_bl(b, op_bits, one);
+    let is_ret = is_ret(b, op_bits, one);
+    let arg0bits = b.decode32(zero);
+    let arg1 = imm_arg1(b, op_bits, zero, arg1_word);
+    let arg1bits = b.decode32(arg1);
 
     // Get output value of dst register, used for mocking the result
     // of memory operations for ALU sub-circuit, and getting the
     // value of LDR operations for the memory consistency sub-circuit.
-    let dst_out = b.select_range(dst, ARG0, ARG0 + N_REG, 1);
-    let cfl_out = ARG0 + N_REG;
+    let dst_out = b.select_range(dst, ARG0, ARG0 + n_reg, 1);
+    let cfl_out = core::array::from_fn(|j| ARG0 + offset_cfl(n_reg) + j);
+
+    // Advance the public tape if op is READ from it
+    let head_out = ARG0 + offset_head(n_reg);
+    let (check_head, pub_val) = read_circ(b, op_bits, field4_bits, zero, head_out, tape, one);
+
+    // Output the value of arg0 if op is OUT
+    let heads = (zero, ARG0 + offset_out(n_reg));
+    let (check_out, check_out_val) = out_circ(b, op_bits, zero, heads, outs, zero, one);
 
     // Compute the result of the ALU at this transition step.
     //
     // Pass zero for the value of (registers referenced by) arg0,
-    // arg1 as well as the conditional Z flag. (todo: what if arg0
+    // arg1 as well as the conditional flags. (todo: what if arg0
     // == pc?)
     let alu_in = AluIn {
-        op,
+        op_bits,
+        is_mem,
         arg0: zero,
-        arg1: zero,
+        arg0bits,
+        arg1,
+        arg1bits,
         arg1_word,
-        cfl_z: zero,
+        cfl: [zero; N_CFL],
         pc: zero,
         field4,
+        field4_bits,
+        pub_val,
     };
-    let (res, z) = alu(b, alu_in, dst_out, zero, one);
+    let mut checks = div_circ(b, &alu_in, dst_out, d, zero, one);
+    let (res, cfl) = alu(b, alu_in, dst_out, zero, one);
 
-    // Output res-dst_out and z-cfl_out (both should be zero)
+    // Output res-dst_out and cfl-cfl_out (all should be zero)
     let check_alu = b.sub(res, dst_out);
-    let check_cfl = b.sub(z, cfl_out);
+    checks.append(&mut vec![check_alu, check_head, check_out, check_out_val]);
 
-    // Conditional flag isn't set when op is STR
-    let tmp = b.xor_bits(&[is_str, one]);
-    let check_cfl = b.mul(tmp, check_cfl);
+    // Conditional flags aren't set when op is STR, i.e they keep
+    // their initial value
+    checks.append(&mut check_cfl(b, is_str, cfl, [zero; N_CFL], cfl_out));
 
-    // Increment pc if op is not RET
+    // Increment pc if op is not RET, and branch to arg1 if op is BL
     let tmp = b.sub(one, is_ret);
     let pc = b.mul(tmp, one);
+    let tmp = b.sub(arg1, pc);
+    let tmp = b.mul(is_bl, tmp);
+    let pc = b.add(&[pc, tmp]);
 
     // Check all in/ out registers except dst are consistent
     let mut regs = vec![(pc, ARG0)];
-    for i in 1..N_REG {
+    for i in 1..n_reg {
         regs.push((zero, ARG0 + i))
     }
     b.check_all_eq_but_one(dst, &regs);
 
-    // Compute the memory address, if instruction is a LDR/STR.
-    // Since the address is loaded from a register, which are all
-    // initialized to 0, and this is the first instructions, then
-    // the any LDR/ STR must use zero as the address.
-    //
-    // We use address 0 for instructions that don't use memory (see
-    // trans_circ), thus mem_addr is 1 (for address 0) if
-    // instruction is a LDR/STR.
-    let mem_addr = b.mul(is_mem, one);
-
-    // Compute the the value read/ written for LDR/STR instructions.
-    // Similar to mem_addr this is zero for the first instruction
-    let mem_val = zero;
+    // Since the registers are all initialized to 0, and this is the
+    // first instruction, then any memory operation uses address 0
+    // and stores the value 0.
+    let mem_in = MemIn {
+        op_bits,
+        is_mem,
+        is_load,
+        is_str,
+        arg0: zero,
+        arg0bits,
+        dst_in: zero,
+        dst_out,
+        m,
+    };
+    let (mut o, mem_addr, v_old, v_new) = mem_circ(b, mem_in, (regions, code), zero, one);
+    checks.append(&mut o);
 
-    (vec![check_alu, check_cfl], mem_addr, mem_val, is_load)
+    (checks, is_ret, mem_addr, v_old, v_new)
 }
 
 /// Input:
 /// - b: builder with the source code as constants
 /// - i: iteration count (0 <= i < time bound t)
-/// - l: number of lines of source code
+/// - instr: ids of the high and low word of the instruction at pc
+/// - wit: ids of the witness values of the memory word accessed and
+///   of the word taken from the prover (see div_circ)
+/// - params: number of registers, range of constants holding the
+///   public tape, range of ids of the values output and memory
+///   regions of the program
+/// - zero: index of constant zero
 /// - one: index of constant one
-/// - two: index of constant two
 ///
-/// Returns: the same as first_transition_circuit
+/// Returns: the same as fst_trans_circ
 fn trans_circ(
     b: &mut builder::Builder<Word>,
     i: usize,
-    l: usize,
+    instr: (usize, usize),
+    wit: (usize, usize),
+    params: TransParams,
     zero: usize,
     one: usize,
-    id_two: usize,
-) -> (Vec<usize>, usize, usize, usize) {
-    let k0 = i * SIZE_LOCAL_ST + ARG0;
-    let k1 = (i + 1) * SIZE_LOCAL_ST + ARG0;
-    let k2 = (i + 2) * SIZE_LOCAL_ST + ARG0;
+) -> (Vec<usize>, usize, usize, usize, usize) {
+    let TransParams {
+        n_reg,
+        tape,
+        outs,
+        regions,
+        code,
+    } = params;
+    let reg_bits = reg_bits(n_reg);
+    let (m, d) = wit;
+    let k0 = i * size_local_st(n_reg) + ARG0;
+    let k1 = (i + 1) * size_local_st(n_reg) + ARG0;
 
     // for (i, w) in (k0..k1).enumerate() {
     //     b.debug(i);
     //     b.debug_wire(w);
     // }
 
-    // Fetch instruction
     let pc = k0 + usize::from(PC);
-    let pc_hi = b.mul_const(id_two, pc);
-    let pc_lo = b.add(&[one, pc_hi]);
-    let instr_hi = b.select_const_range(pc_hi, ARG0, ARG0 + 2 * l, 1);
-    let instr_lo = b.select_const_range(pc_lo, ARG0, ARG0 + 2 * l + 1, 1);
+    let (instr_hi, instr_lo) = instr;
 
     // Decode instruction
-    let (op, dst, arg0, is_mem, is_load, is_ret, field4) = gadgets::decode_hi_instr32(b, instr_hi);
-    let (arg1, arg1_word) = gadgets::decode_lo_instr32(b, instr_lo);
+    let HiInstr {
+        op_bits,
+        dst,
+        arg0,
+        is_mem,
+        is_load,
+        field4,
+        field4_bits,
+        ..
+    } = gadgets::decode_hi_instr32(b, instr_hi, reg_bits);
+    let (arg1, arg1_word) = gadgets::decode_lo_instr32(b, instr_lo, reg_bits);
 
     let is_str = b.xor_bits(&[is_mem, is_load]);
+    let is_bl = is_bl(b, op_bits, one);
+    let is_ret = is_ret(b, op_bits, one);
 
     // Get value of registers refered to by dst, arg0 and arg1 as
-    // well as value of Z flag
-    let dst_in = b.select_range(dst, k0, k1 - N_CFL, 1);
-    let arg0 = b.select_range(arg0, k0, k1 - N_CFL, 1);
-    let arg1 = b.select_range(arg1, k0, k1 - N_CFL, 1);
-    let cfl_z = k1 - N_CFL;
-
-    let dst_out = b.select_range(dst, k1, k2 - N_CFL, 1);
-    let cfl_z_out = k2 - N_CFL;
+    // well as value of the conditional flags
+    let dst_in = b.select_range(dst, k0, k0 + n_reg, 1);
+    let arg0 = b.select_range(arg0, k0, k0 + n_reg, 1);
+    let arg1 = b.select_range(arg1, k0, k0 + n_reg, 1);
+    let arg1 = imm_arg1(b, op_bits, arg1, arg1_word);
+    let arg0bits = b.decode32(arg0);
+    let arg1bits = b.decode32(arg1);
+    let cfl_in = core::array::from_fn(|j| k0 + offset_cfl(n_reg) + j);
+
+    let dst_out = b.select_range(dst, k1, k1 + n_reg, 1);
+    let cfl_out = core::array::from_fn(|j| k1 + offset_cfl(n_reg) + j);
+
+    // Advance the public tape if op is READ from it
+    let head_in = k0 + offset_head(n_reg);
+    let head_out = k1 + offset_head(n_reg);
+    let (check_head, pub_val) = read_circ(b, op_bits, field4_bits, head_in, head_out, tape, one);
+
+    // Output the value of arg0 if op is OUT
+    let heads = (k0 + offset_out(n_reg), k1 + offset_out(n_reg));
+    let (check_out, check_out_val) = out_circ(b, op_bits, arg0, heads, outs, zero, one);
 
     let alu_in = AluIn {
-        op,
+        op_bits,
+        is_mem,
         arg0,
+        arg0bits,
         arg1,
+        arg1bits,
         arg1_word,
-        cfl_z,
+        cfl: cfl_in,
         pc,
         field4,
+        field4_bits,
+        pub_val,
     };
-    let (res, z) = alu(b, alu_in, dst_out, zero, one);
+    let mut checks = div_circ(b, &alu_in, dst_out, d, zero, one);
+    let (res, cfl) = alu(b, alu_in, dst_out, zero, one);
 
-    // Ouput res-dst_out and z-cfl_out - both should be zero if the
+    // Ouput res-dst_out and cfl-cfl_out - all should be zero if the
     // witness satisfies the circuit.
     let check_alu = b.sub(res, dst_out);
-    let check_cfl = b.sub(z, cfl_z_out);
+    checks.append(&mut vec![check_alu, check_head, check_out, check_out_val]);
 
-    // Conditional flag isn't set when op is STR
-    let tmp = b.xor_bits(&[is_str, one]);
-    let check_cfl = b.mul(tmp, check_cfl);
+    // Conditional flags aren't set when op is STR
+    checks.append(&mut check_cfl(b, is_str, cfl, cfl_in, cfl_out));
 
-    // Increment pc if op is not ret
+    // Increment pc if op is not ret, and branch to arg1 if op is BL
     let tmp = b.sub(one, is_ret);
     let pc = b.add(&[k0 + usize::from(PC), tmp]);
+    let tmp = b.sub(arg1, pc);
+    let tmp = b.mul(is_bl, tmp);
+    let pc = b.add(&[pc, tmp]);
 
     // Check all in/ out registers except dst are consistent
     let mut regs = vec![(pc, k1)];
-    for i in 1..N_REG {
+    for i in 1..n_reg {
         regs.push((k0 + i, k1 + i))
     }
     b.check_all_eq_but_one(dst, &regs);
 
-    // Compute the memory address, if instruction is a LDR/STR.
-    // Set mem_addr to 0 for instructions that don't use memory,
-    // and add 1 to the actual address. This makes the highest
-    // address unavailable to programs.
-    let tmp = b.add(&[arg0, one]);
-    let mem_addr = b.mul(is_mem, tmp);
+    let mem_in = MemIn {
+        op_bits,
+        is_mem,
+        is_load,
+        is_str,
+        arg0,
+        arg0bits,
+        dst_in,
+        dst_out,
+        m,
+    };
+    let (mut o, mem_addr, v_old, v_new) = mem_circ(b, mem_in, (regions, code), zero, one);
+    checks.append(&mut o);
+
+    (checks, is_ret, mem_addr, v_old, v_new)
+}
+
+/// Returns (check, val) where check is zero only if the position
+/// of the public tape is incremented by one when op is READ from the
+/// public tape, and otherwise unchanged, and val is the value read
+/// from the public tape.
+///
+/// The constants in range tape are a dummy value followed by the
+/// values of the public tape, so the value read is selected using
+/// head_out. Reading past the end of the public tape makes head_out
+/// out of range, and the circuit unsatisfiable.
+fn read_circ(
+    b: &mut Builder<Word>,
+    op_bits: usize,
+    field4_bits: usize,
+    head_in: usize,
+    head_out: usize,
+    tape: (usize, usize),
+    one: usize,
+) -> (usize, usize) {
+    let is_read = is_op(b, op_bits, 0b10011000, one);
+    let is_pub = b.mul(is_read, field4_bits);
+    let tmp = b.add(&[head_in, is_pub]);
+    let check = b.sub(head_out, tmp);
+    let val = b.select_const_range(head_out, tape.0, tape.1, 1);
+    (check, val)
+}
+
+/// Returns (check, check_val) where check is zero only if the number
+/// of values output is incremented by one when op is OUT, and
+/// otherwise unchanged, and check_val is zero only if the value val
+/// output by OUT is the next of the witness values in range outs.
+///
+/// Like the value read from the public tape (see read_circ), the
+/// value output is selected using the number of values output after
+/// the step, so outputting more than n_out values makes the circuit
+/// unsatisfiable.
+fn out_circ(
+    b: &mut Builder<Word>,
+    op_bits: usize,
+    val: usize,
+    heads: (usize, usize),
+    outs: (usize, usize),
+    zero: usize,
+    one: usize,
+) -> (usize, usize) {
+    let (head_in, head_out) = heads;
+    let is_out = is_op(b, op_bits, 0b110000, one);
+    let tmp = b.add(&[head_in, is_out]);
+    let check = b.sub(head_out, tmp);
+    let ids = &core::iter::once(zero)
+        .chain(outs.0..outs.1)
+        .collect::<Vec<_>>();
+    let out = b.select(head_out, ids);
+    let tmp = b.sub(val, out);
+    let check_val = b.mul(is_out, tmp);
+    (check, check_val)
+}
+
+struct MemIn {
+    op_bits: usize,
+    is_mem: usize,
+    is_load: usize,
+    is_str: usize,
+    arg0: usize,
+    arg0bits: usize,
+    dst_in: usize,
+    dst_out: usize,
+    m: usize,
+}
+
+/// Input:
+///   - op_bits: bit-decomposition of the opcode of instruction
+///   - is_mem/ is_load/ is_str: 1 only if instruction is a memory
+///     operation/ load/ store
+///   - arg0, arg0bits: value of arg0 (holding the address) and its
+///     bit-decomposition
+///   - dst_in, dst_out: value of destination register before and
+///     after the instruction (the value stored/ loaded)
+///   - m: value of the memory word accessed, before the access
+///
+/// Output (checks, addr, v_old, v_new) where checks should all be
+/// zero and the rest is as described for fst_trans_circ.
+///
+/// Byte and halfword accesses are checked against the whole memory
+/// word m, so the memory consistency circuit only needs to handle
+/// word accesses. The address of the word must be in one of the
+/// regions, if any (see region_circ). If code is set, i.e the
+/// program is in memory at ADR_CODE, stores must not change the
+/// words from ADR_CODE.
+fn mem_circ(
+    b: &mut Builder<Word>,
+    in_: MemIn,
+    (regions, code): (&[Region], bool),
+    zero: usize,
+    one: usize,
+) -> (Vec<usize>, usize, usize, usize) {
+    let MemIn {
+        op_bits,
+        is_mem,
+        is_load,
+        is_str,
+        arg0,
+        arg0bits,
+        dst_in,
+        dst_out,
+        m,
+    } = in_;
+
+    // Bit 2 of the opcode is set for LDRB/ STRB and bit 3 for LDRH/
+    // STRH, which address bytes rather than words.
+    let is_byte = b.and_bits(is_mem, op_bits + 2);
+    let is_half = b.and_bits(is_mem, op_bits + 3);
+    let is_sub = b.add(&[is_byte, is_half]);
+
+    // Compute the address of the word, i.e arg0 / 4 for byte and
+    // halfword accesses. Set addr to 0 for instructions that don't
+    // use memory, and add 1 to the actual address. This makes the
+    // highest address unavailable to programs.
+    let mut xs = (arg0bits + 2..arg0bits + 32).chain(core::iter::repeat(zero));
+    let arg0_4 = b.encode32_range(core::array::from_fn(|_| xs.next().unwrap()));
+    let tmp = b.sub(arg0_4, arg0);
+    let tmp = b.mul(is_sub, tmp);
+    let word = b.add(&[arg0, tmp]);
+    let tmp = b.add(&[word, is_mem]);
+    let addr = b.mul(is_mem, tmp);
+
+    // Word accesses must not use the highest address, which would
+    // wrap around to the address of instructions that don't use
+    // memory (byte and halfword accesses can't reach it)
+    let is_max = (1..32).fold(arg0bits, |acc, k| b.and_bits(acc, arg0bits + k));
+    let is_word = b.sub(is_mem, is_sub);
+    let check_range = b.mul(is_word, is_max);
+    let mut checks = region_circ(b, word, (is_mem, is_str), regions, zero, one);
+    checks.push(check_range);
+
+    // Instructions are fetched from the words from ADR_CODE, i.e the
+    // words with the most significant bit set, which byte and
+    // halfword accesses can't reach, so word stores there could make
+    // the program fetched differ from the program loaded
+    if code {
+        let tmp = b.and_bits(is_word, arg0bits + 31);
+        let check_code = b.mul(is_str, tmp);
+        checks.push(check_code);
+    }
+
+    // Halfword addresses must be even
+    let check_align = b.mul(is_half, arg0bits);
+
+    // Index of the byte and halfword in the word
+    let mut xs = [arg0bits, arg0bits + 1]
+        .into_iter()
+        .chain(core::iter::repeat(zero));
+    let idx_b = b.encode32_range(core::array::from_fn(|_| xs.next().unwrap()));
+    let idx_h = arg0bits + 1;
+
+    // Value loaded: m, or the byte/ halfword of m
+    let m_bits = b.decode32(m);
+    let bytes = (0..4)
+        .map(|k| b.encode8(m_bits + 8 * k))
+        .collect::<Vec<_>>();
+    let halves = (0..2)
+        .map(|k| {
+            let mut xs = (m_bits + 16 * k..m_bits + 16 * (k + 1)).chain(core::iter::repeat(zero));
+            b.encode32_range(core::array::from_fn(|_| xs.next().unwrap()))
+        })
+        .collect::<Vec<_>>();
+    let byte = b.select(idx_b, &bytes);
+    let half = b.select(idx_h, &halves);
+    let tmp1 = b.sub(byte, m);
+    let tmp1 = b.mul(is_byte, tmp1);
+    let tmp2 = b.sub(half, m);
+    let tmp2 = b.mul(is_half, tmp2);
+    let loaded = b.add(&[m, tmp1, tmp2]);
+    let tmp = b.sub(dst_out, loaded);
+    let check_load = b.mul(is_load, tmp);
+
+    // Value stored: dst_in, or m with a byte/ halfword replaced by
+    // the least significant byte/ halfword of dst_in. Compute m with
+    // each possible byte (halfword) replaced and select the right one.
+    let src_bits = b.decode32(dst_in);
+    let replace = |b: &mut Builder<Word>, k: usize, n: usize| {
+        let xs = core::array::from_fn(|j| {
+            if j / n == k {
+                src_bits + j % n
+            } else {
+                m_bits + j
+            }
+        });
+        b.encode32_range(xs)
+    };
+    let with_bytes = (0..4).map(|k| replace(b, k, 8)).collect::<Vec<_>>();
+    let with_halves = (0..2).map(|k| replace(b, k, 16)).collect::<Vec<_>>();
+    let with_byte = b.select(idx_b, &with_bytes);
+    let with_half = b.select(idx_h, &with_halves);
+    let tmp1 = b.sub(with_byte, dst_in);
+    let tmp1 = b.mul(is_byte, tmp1);
+    let tmp2 = b.sub(with_half, dst_in);
+    let tmp2 = b.mul(is_half, tmp2);
+    let stored = b.add(&[dst_in, tmp1, tmp2]);
+
+    // The memory word is only changed by stores
+    let tmp = b.sub(stored, m);
+    let tmp = b.mul(is_str, tmp);
+    let v_new = b.add(&[m, tmp]);
+
+    // Stores don't change any register (dst is the register holding
+    // the value stored)
+    let tmp = b.sub(dst_out, dst_in);
+    let check_str = b.mul(is_str, tmp);
+
+    checks.append(&mut vec![check_align, check_load, check_str]);
+    (checks, addr, m, v_new)
+}
 
-    // Compute the the value read/ written for LDR/STR instructions.
-    // The value is only used if is_load is set, so garbage is sent
-    // for instructions that don't access memory.
-    let tmp1 = b.mul(is_load, dst_out);
-    let tmp2 = b.sub(one, is_load);
-    let tmp3 = b.mul(tmp2, dst_in);
-    let mem_val = b.add(&[tmp1, tmp3]);
+/// Returns the ids of checks that are zero only if the memory word at
+/// address adr is in one of the regions when is_mem is 1, and in one
+/// of the writable regions when is_str is 1. Programs without
+/// regions may access all of memory, so then there are no checks.
+///
+/// The address is compared with the bounds of each region, which are
+/// constants, so the bits of the bounds are just the ids of zero and
+/// one.
+fn region_circ(
+    b: &mut Builder<Word>,
+    adr: usize,
+    is_mem_str: (usize, usize),
+    regions: &[Region],
+    zero: usize,
+    one: usize,
+) -> Vec<usize> {
+    if regions.is_empty() {
+        return vec![];
+    }
+    let (is_mem, is_str) = is_mem_str;
+    let adr_bits = b.decode32(adr);
+    let xs = &(adr_bits..adr_bits + 32).collect::<Vec<_>>();
+    let bits = |c: Word| {
+        (0..32)
+            .map(|k| if (c >> k) & 1 == 1 { one } else { zero })
+            .collect::<Vec<_>>()
+    };
+
+    // Whether adr is in any region, and in any writable region
+    let (mut in_any, mut in_rw) = (zero, zero);
+    for r in regions {
+        let (lt_start, _) = gadgets::word_comparator(b, xs, &bits(r.start), one);
+        let (lt_end, _) = gadgets::word_comparator(b, xs, &bits(r.end), one);
+        let ge_start = b.xor_bits(&[lt_start, one]);
+        let in_r = b.and_bits(ge_start, lt_end);
+        in_any = b.or_bits(in_any, in_r);
+        if r.perm == Perm::ReadWrite {
+            in_rw = b.or_bits(in_rw, in_r);
+        }
+    }
+    let tmp = b.xor_bits(&[in_any, one]);
+    let check_read = b.mul(is_mem, tmp);
+    let tmp = b.xor_bits(&[in_rw, one]);
+    let check_write = b.mul(is_str, tmp);
+    vec![check_read, check_write]
+}
 
-    (vec![check_alu, check_cfl], mem_addr, mem_val, is_load)
+/// Returns the ids of checks that the conditional flags cfl_out of
+/// the next state are equal to the flags cfl computed by the ALU, or
+/// to the flags cfl_in of the current state if keep is 1.
+fn check_cfl(
+    b: &mut Builder<Word>,
+    keep: usize,
+    cfl: [usize; N_CFL],
+    cfl_in: [usize; N_CFL],
+    cfl_out: [usize; N_CFL],
+) -> Vec<usize> {
+    (0..N_CFL)
+        .map(|j| {
+            // compute cfl + keep*(cfl_in - cfl)
+            let tmp = b.sub(cfl_in[j], cfl[j]);
+            let tmp = b.mul(keep, tmp);
+            let tmp = b.add(&[cfl[j], tmp]);
+            b.sub(tmp, cfl_out[j])
+        })
+        .collect()
 }
 
 struct AluIn {
-    op: usize,
+    op_bits: usize,
+    is_mem: usize,
     arg0: usize,
+    arg0bits: usize,
     arg1: usize,
+    arg1bits: usize,
     arg1_word: usize,
-    cfl_z: usize,
+    cfl: [usize; N_CFL],
     pc: usize,
     field4: usize,
+    field4_bits: usize,
+    pub_val: usize,
 }
 
 /// Input:
-///   - op: opcode of instruction
+///   - op_bits: bit-decomposition of the opcode of instruction
+///   - is_mem: 1 only if instruction is a memory operation
 ///   - arg0: value of arg0 (as a 4 bit register).
-///   - arg1: value of arg1 (as a 4 bit register).
+///   - arg0bits: bit-decomposition of arg0.
+///   - arg1: value of arg1 (as a 4 bit register), or the constant
+///     for instructions with a constant second operand.
+///   - arg1bits: bit-decomposition of arg1.
 ///   - arg1_w: value of arg1 (as a 32 bit word).
-///   - cfl_z: value of the (isZero) Z conditional flag.
+///   - cfl: value of the conditional flags, indexed by Cond.
+///   - pub_val: value at the position of the public tape.
 ///   - dst_out: value of destination register. This should be equal
 ///   to the output of the alu. This is used for mocking the result
 ///   of memory operations.
 ///
-/// Output pair (res, z) where:
+/// Output pair (res, cfl) where:
 ///   - res: Value of applying op to arg0, arg1 OR dst_out of op is a
 ///   memory operation (which are checked by a seperate memory
 ///   consistency circuit)
-///   - z: is the boolean value of the Z flag.
+///   - cfl: the boolean values of the conditional flags set by op,
+///     indexed by Cond.
 fn alu(
     b: &mut Builder<Word>,
     in_: AluIn,
     dst_out: usize,
     zero: usize,
     one: usize,
-) -> (usize, usize) {
+) -> (usize, [usize; N_CFL]) {
     // Compute each possible operation of the architecture in order
     // of the encoding of opcodes. Then select the correct value
     // using the opcode.
-    let a2 = dst_out; // str
-    let a3 = dst_out; // ldr
-
     let a4 = b.add(&[in_.arg0, in_.arg1]); // add
     let a8 = b.sub(in_.arg0, in_.arg1); // sub
     let a12 = in_.arg1; // mov register
     let a16 = in_.arg1_word; // mov constant
     let a20 = in_.arg1; // b
-                        // b z: compute (pc + 1) + cfl_z*(arg1 - (pc + 1))
-    let tmp1 = b.add(&[in_.pc, one]);
-    let tmp2 = b.sub(in_.arg1, tmp1);
-    let tmp3 = b.mul(in_.cfl_z, tmp2);
-    let a24 = b.add(&[tmp1, tmp3]);
+
+    // b cond: compute (pc + 1) + taken*(arg1 - (pc + 1))
+    let taken = cond_holds(b, in_.cfl, in_.field4_bits, zero, one);
+    let pc_next = b.add(&[in_.pc, one]);
+    let tmp2 = b.sub(in_.arg1, pc_next);
+    let tmp3 = b.mul(taken, tmp2);
+    let a24 = b.add(&[pc_next, tmp3]);
+
+    // b cond relative: compute (pc + 1) + taken*(pc + arg1 - (pc + 1)),
+    // where pc + arg1 is reduced mod 2^32 by subtracting 2^32 if the
+    // offset arg1 is negative
+    let id_max = b.push_const(Word::MAX);
+    let max = b.const_(id_max);
+    let two_32 = b.add(&[max, one]);
+    let wrap = b.mul(in_.arg1bits + 31, two_32);
+    let tmp1 = b.add(&[in_.pc, in_.arg1]);
+    let tmp1 = b.sub(tmp1, wrap);
+    let tmp2 = b.sub(tmp1, pc_next);
+    let tmp3 = b.mul(taken, tmp2);
+    let a204 = b.add(&[pc_next, tmp3]);
+
+    // csel: compute arg1 + taken*(arg0 - arg1)
+    let tmp1 = b.sub(in_.arg0, in_.arg1);
+    let tmp2 = b.mul(taken, tmp1);
+    let a156 = b.add(&[in_.arg1, tmp2]);
+
+    // udiv/ urem: the result is taken from the prover and checked by
+    // div_circ
+    let a40 = dst_out;
+    let a44 = dst_out;
+
+    // out: the register is unchanged
+    let a48 = in_.arg0;
 
     let a32 = in_.arg1; // ret register
     let a36 = in_.arg1_word; // ret constant
 
-    let arg0bits = b.decode32(in_.arg0);
-    let arg1bits = b.decode32(in_.arg1);
+    let arg0bits = in_.arg0bits;
+    let arg1bits = in_.arg1bits;
+
+    // The shift amount is field4, or the 5 lowest bits of arg1 for
+    // the register-amount shifts and rotates
+    let is_shr_r = is_op(b, in_.op_bits, 0b10001000, one);
+    let is_shl_r = is_op(b, in_.op_bits, 0b10001100, one);
+    let is_rotr_r = is_op(b, in_.op_bits, 0b10010000, one);
+    let is_shift_r = b.add(&[is_shr_r, is_shl_r, is_rotr_r]);
+    let mut amt = (arg1bits..arg1bits + 5).chain(core::iter::repeat(zero));
+    let amt = b.encode32_range(core::array::from_fn(|_| amt.next().unwrap()));
+    let tmp = b.sub(amt, in_.field4);
+    let tmp = b.mul(is_shift_r, tmp);
+    let shamt = b.add(&[in_.field4, tmp]);
     // --- bitwise operations
     // and/ xor
     let a0 = gadgets::bitwise_and_u32_bits(b, arg0bits, arg1bits);
@@ -481,8 +1340,8 @@ fn alu(
     });
     // shr 0 arg0, shr 1 arg0, ..., shr 31 arg0
     let shrs = &[in_.arg0].into_iter().chain(shrs).collect::<Vec<_>>();
-    // shr field4 arg0
-    let a64 = b.select(in_.field4, shrs);
+    // shr shamt arg0
+    let a64 = b.select(shamt, shrs);
 
     // rotr
     let rotrs = (1..32).map(|i| {
@@ -492,17 +1351,49 @@ fn alu(
     });
     // rotr 0 arg0, rotr 1 arg0, ..., rotr 31 arg0
     let rotrs = &[in_.arg0].into_iter().chain(rotrs).collect::<Vec<_>>();
-    // rotr field4 arg0
-    let a68 = b.select(in_.field4, rotrs);
+    // rotr shamt arg0
+    let a68 = b.select(shamt, rotrs);
 
     // print
     let a72 = dst_out;
 
-    // todo: select(in_.op / 4, ids) instead
-    let mut ids = [ARG0; 73];
+    // read: the value read from the public tape is pub_val, and the
+    // value read from the private tape is unconstrained
+    let tmp = b.sub(in_.pub_val, dst_out);
+    let tmp = b.mul(in_.field4_bits, tmp);
+    let a152 = b.add(&[dst_out, tmp]);
+
+    // shl
+    let shls = (1..32).map(|i| {
+        // encode the bits x(i) ... x31 x0 ... 0 0 0
+        let mut xs = (0..i).map(|_| zero).chain(arg0bits..arg0bits + 32 - i);
+        b.encode32_range(core::array::from_fn(|_| xs.next().unwrap()))
+    });
+    // shl 0 arg0, shl 1 arg0, ..., shl 31 arg0
+    let shls = &[in_.arg0].into_iter().chain(shls).collect::<Vec<_>>();
+    // shl shamt arg0
+    let a132 = b.select(shamt, shls);
+
+    // orr: x | y = x + y - (x & y)
+    let tmp = b.add(&[in_.arg0, in_.arg1]);
+    let a92 = b.sub(tmp, a0);
+
+    // mvn: not x = -x - 1
+    let tmp = b.sub(zero, in_.arg0);
+    let a128 = b.sub(tmp, one);
+
+    // --- multiplication
+    // mul/ umulh: the lower and upper word of the product, which the
+    // circuit doesn't reduce mod 2^32
+    let (a76, a80) = gadgets::mul_u32_bits(b, arg0bits, arg1bits, zero);
+
+    // cmp/ tst: the destination is pc, and only the conditional
+    // flags are set (see below)
+    let a84 = pc_next;
+    let a88 = pc_next;
+
+    let mut ids = [ARG0; 221];
     ids[0] = a0;
-    ids[2] = a2;
-    ids[3] = a3;
     ids[4] = a4;
     ids[8] = a8;
     ids[12] = a12;
@@ -512,35 +1403,266 @@ fn alu(
     ids[28] = a28;
     ids[32] = a32;
     ids[36] = a36;
+    ids[40] = a40;
+    ids[44] = a44;
+    ids[48] = a48;
     ids[64] = a64;
     ids[68] = a68;
     ids[72] = a72;
-
-    let res = b.select(in_.op, &ids);
-
-    // Compute the value of the Z flag by destructing res into its
-    // bit-decomposition, converting each bit to Z2, OR-ing them
+    ids[76] = a76;
+    ids[80] = a80;
+    ids[84] = a84;
+    ids[88] = a88;
+    ids[92] = a92;
+    ids[128] = a128;
+    ids[132] = a132;
+    ids[136] = a64;
+    ids[140] = a132;
+    ids[144] = a68;
+    ids[148] = pc_next; // bl register
+    ids[152] = a152;
+    ids[156] = a156;
+    // and/ add/ sub/ cmp/ xor with a constant
+    ids[192] = a0;
+    ids[196] = a4;
+    ids[200] = a8;
+    ids[204] = a204; // b cond relative
+    ids[208] = pc_next; // bl constant
+    ids[212] = a84;
+    ids[216] = a24; // b cond to constant
+    ids[220] = a28;
+
+    // Only the opcodes of the memory operations (str and ldr) are not
+    // multiples of 4, so select the result using op / 4, and use the
+    // value of dst_out for memory operations.
+    let mut op_4 = (in_.op_bits + 2..in_.op_bits + 8).chain(core::iter::repeat(zero));
+    let op_4 = b.encode32_range(core::array::from_fn(|_| op_4.next().unwrap()));
+    let ids = ids.into_iter().step_by(4).collect::<Vec<_>>();
+    let res = b.select(op_4, &ids);
+    let tmp = b.sub(dst_out, res);
+    let tmp = b.mul(in_.is_mem, tmp);
+    let res = b.add(&[res, tmp]);
+
+    // The conditional flags are computed from res, except for cmp
+    // and tst which set the flags of sub and and respectively.
+    let is_cmp = is_op(b, in_.op_bits, 0b1010100, one);
+    let is_cmp_c = is_op(b, in_.op_bits, 0b11010100, one);
+    let is_cmp = b.add(&[is_cmp, is_cmp_c]);
+    let is_tst = is_op(b, in_.op_bits, 0b1011000, one);
+    let tmp1 = b.sub(a8, res);
+    let tmp1 = b.mul(is_cmp, tmp1);
+    let tmp2 = b.sub(a0, res);
+    let tmp2 = b.mul(is_tst, tmp2);
+    let cfl_res = b.add(&[res, tmp1, tmp2]);
+
+    // Compute the value of the Z flag by destructing cfl_res into
+    // its bit-decomposition, converting each bit to Z2, OR-ing them
     // all together and negating the ouput.
-    let decode_res = b.decode32(res);
+    let decode_res = b.decode32(cfl_res);
     let mut tmp = b.or_bits(decode_res, decode_res + 1); // todo: a2b?
     for i in 2..32 {
         tmp = b.or_bits(tmp, decode_res + i)
     }
     let z = b.xor_bits(&[tmp, one]);
-    (res, z)
+
+    // The N flag is the most significant bit of cfl_res
+    let n = decode_res + 31;
+
+    // The C and V flags are only set by add and sub/ cmp, and can be
+    // computed from the most significant bits x, y of the arguments
+    // and r of the result:
+    let (x, y, r) = (arg0bits + 31, arg1bits + 31, decode_res + 31);
+    let x_xor_y = b.xor_bits(&[x, y]);
+    let x_xor_r = b.xor_bits(&[x, r]);
+    let not_r = b.sub(one, r);
+
+    // add: C = x*y + (x xor y)*(1 - r)
+    //      V = (1 - (x xor y))*(x xor r)
+    let tmp1 = b.and_bits(x, y);
+    let tmp2 = b.and_bits(x_xor_y, not_r);
+    let c_add = b.add(&[tmp1, tmp2]);
+    let x_xnor_y = b.sub(one, x_xor_y);
+    let v_add = b.and_bits(x_xnor_y, x_xor_r);
+
+    // sub computes x + (not y) + 1, so
+    //      C = x*(1 - y) + (1 - (x xor y))*(1 - r)
+    //      V = (x xor y)*(x xor r)
+    let not_y = b.sub(one, y);
+    let tmp1 = b.and_bits(x, not_y);
+    let tmp2 = b.and_bits(x_xnor_y, not_r);
+    let c_sub = b.add(&[tmp1, tmp2]);
+    let v_sub = b.and_bits(x_xor_y, x_xor_r);
+
+    let is_add = is_op(b, in_.op_bits, 0b100, one);
+    let is_add_c = is_op(b, in_.op_bits, 0b11000100, one);
+    let is_add = b.add(&[is_add, is_add_c]);
+    let is_sub = is_op(b, in_.op_bits, 0b1000, one);
+    let is_sub_c = is_op(b, in_.op_bits, 0b11001000, one);
+    let is_sub = b.add(&[is_sub, is_sub_c, is_cmp]);
+    let tmp1 = b.mul(is_add, c_add);
+    let tmp2 = b.mul(is_sub, c_sub);
+    let c = b.add(&[tmp1, tmp2]);
+    let tmp1 = b.mul(is_add, v_add);
+    let tmp2 = b.mul(is_sub, v_sub);
+    let v = b.add(&[tmp1, tmp2]);
+
+    let mut cfl = [zero; N_CFL];
+    cfl[Cond::Z as usize] = z;
+    cfl[Cond::C as usize] = c;
+    cfl[Cond::N as usize] = n;
+    cfl[Cond::V as usize] = v;
+    (res, cfl)
+}
+
+/// Returns the ids of checks that dst_out is the quotient (UDIV) or
+/// the remainder (UREM) of dividing arg0 by arg1, where d is the
+/// witness value of the remainder (UDIV) or the quotient (UREM).
+///
+/// Computing the division in the circuit is expensive, so instead
+/// the quotient q and remainder r taken from the prover are checked:
+///
+///   arg0 = q*arg1 + r (without wrapping) and r < arg1
+///
+/// or, if arg1 is 0, q = 0 and r = arg0 (as in interpreter::div_rem).
+fn div_circ(
+    b: &mut Builder<Word>,
+    in_: &AluIn,
+    dst_out: usize,
+    d: usize,
+    zero: usize,
+    one: usize,
+) -> Vec<usize> {
+    let is_udiv = is_op(b, in_.op_bits, 0b101000, one);
+    let is_urem = is_op(b, in_.op_bits, 0b101100, one);
+    let is_div = b.add(&[is_udiv, is_urem]);
+
+    // q = d + is_udiv*(dst_out - d), and r is the other one
+    let tmp = b.sub(dst_out, d);
+    let tmp = b.mul(is_udiv, tmp);
+    let q = b.add(&[d, tmp]);
+    let tmp = b.add(&[dst_out, d]);
+    let r = b.sub(tmp, q);
+
+    // The product q*arg1 doesn't wrap if its upper word is 0, and
+    // adding r doesn't wrap if the sum isn't less than r
+    let q_bits = b.decode32(q);
+    let r_bits = b.decode32(r);
+    let hi = gadgets::umulh_u32_bits(b, q_bits, in_.arg1bits, zero);
+    let lo = b.mul(q, in_.arg1);
+    let sum = b.add(&[lo, r]);
+    let sum_bits = b.decode32(sum);
+    let bits = |x0: usize| (x0..x0 + 32).collect::<Vec<_>>();
+    let (carry, _) = gadgets::word_comparator(b, &bits(sum_bits), &bits(r_bits), one);
+    let (r_lt, _) = gadgets::word_comparator(b, &bits(r_bits), &bits(in_.arg1bits), one);
+    let (_, arg1_z) = gadgets::word_comparator(b, &bits(in_.arg1bits), &[zero; 32], one);
+
+    let tmp = b.sub(in_.arg0, sum);
+    let check_sum = b.mul(is_div, tmp);
+    let check_hi = b.mul(is_div, hi);
+    let check_carry = b.mul(is_div, carry);
+    // r < arg1 and arg1 = 0 are exclusive, and one of them must hold
+    let tmp = b.sub(one, r_lt);
+    let tmp = b.sub(tmp, arg1_z);
+    let check_r = b.mul(is_div, tmp);
+    let tmp = b.mul(arg1_z, q);
+    let check_q = b.mul(is_div, tmp);
+
+    vec![check_sum, check_hi, check_carry, check_r, check_q]
+}
+
+/// Returns the value of the second operand. The two most significant
+/// bits of the opcode are set for instructions with a constant second
+/// operand, in which case this is arg1_word, and otherwise arg1.
+fn imm_arg1(b: &mut Builder<Word>, op_bits: usize, arg1: usize, arg1_word: usize) -> usize {
+    let is_imm = b.and_bits(op_bits + 6, op_bits + 7);
+    let tmp = b.sub(arg1_word, arg1);
+    let tmp = b.mul(is_imm, tmp);
+    b.add(&[arg1, tmp])
+}
+
+/// Returns 1 if the opcode with bit-decomposition op_bits is BL (with
+/// a register or a constant target), and 0 otherwise.
+fn is_bl(b: &mut Builder<Word>, op_bits: usize, one: usize) -> usize {
+    let is_bl_r = is_op(b, op_bits, 0b10010100, one);
+    let is_bl_c = is_op(b, op_bits, 0b11010000, one);
+    b.add(&[is_bl_r, is_bl_c])
+}
+
+/// Returns 1 if the opcode with bit-decomposition op_bits is RET (with
+/// a register or a constant), and 0 otherwise.
+fn is_ret(b: &mut Builder<Word>, op_bits: usize, one: usize) -> usize {
+    let is_ret_r = is_op(b, op_bits, 0b100000, one);
+    let is_ret_c = is_op(b, op_bits, 0b100100, one);
+    b.add(&[is_ret_r, is_ret_c])
+}
+
+/// Returns 1 if the opcode with bit-decomposition op_bits is op, and
+/// 0 otherwise.
+fn is_op(b: &mut Builder<Word>, op_bits: usize, op: u8, one: usize) -> usize {
+    let lits = (0..8)
+        .map(|i| {
+            if (op >> i) & 1 == 1 {
+                op_bits + i
+            } else {
+                b.sub(one, op_bits + i)
+            }
+        })
+        .collect::<Vec<_>>();
+    lits[1..].iter().fold(lits[0], |acc, x| b.and_bits(acc, *x))
+}
+
+/// Returns 1 if the condition code with bit-decomposition cc (as
+/// encoded in field #4) holds for the conditional flags cfl, and 0
+/// otherwise.
+///
+/// The least significant bit of the condition code negates the
+/// condition, so we select one of 8 base conditions using the
+/// remaining bits and xor the result with the least significant
+/// bit. Code 0b1110 is always true.
+fn cond_holds(
+    b: &mut Builder<Word>,
+    cfl: [usize; N_CFL],
+    cc: usize,
+    zero: usize,
+    one: usize,
+) -> usize {
+    let z = cfl[Cond::Z as usize];
+    let c = cfl[Cond::C as usize];
+    let n = cfl[Cond::N as usize];
+    let v = cfl[Cond::V as usize];
+
+    let not_z = b.sub(one, z);
+    let hi = b.and_bits(c, not_z);
+    let ge = b.xor_bits(&[n, v, one]);
+    let gt = b.and_bits(not_z, ge);
+
+    // eq, hs, mi, vs, hi, ge, gt, al
+    let mut conds = vec![z, c, n, v, hi, ge, gt, one];
+    // Field #4 also holds shift amounts, so every value of the upper
+    // four bits must select some alternative
+    conds.resize(16, zero);
+    let i = b.encode4(cc + 1);
+    let base = b.select(i, &conds);
+    b.xor_bits(&[base, cc])
 }
 
 #[cfg(test)]
 mod test {
     use backend::ProofCtx;
-    use utils::circuit::eval32;
+    use utils::circuit::{eval32, eval32_open};
 
+    use crate::miniram::interpreter::{code_words, interpret, interpret_von_neumann, MemAccess};
+    use crate::miniram::lang::n_reg;
     use crate::miniram::lang::Prog;
     use crate::miniram::lang::Word;
+    use crate::miniram::lang::ADR_CODE;
     use crate::miniram::programs;
     use crate::miniram::programs::*;
 
-    use super::{encode_witness, generate_circuit};
+    use super::{
+        convert_localstates, encode_witness, generate_circuit, generate_circuit_private,
+        program_digest, size_local_st, Arch,
+    };
 
     #[test]
     fn encod_witness() {
@@ -548,14 +1670,14 @@ mod test {
         let p = &mul_eq();
         let args = vec![2, 2, 4];
         let ctx = &mut ProofCtx::new_deterministic();
-        encode_witness(p, args, t, ctx).unwrap();
+        encode_witness(p, args, vec![], vec![], t, Arch::Harvard, ctx).unwrap();
     }
 
     #[test]
     fn gen_circuit() {
         let t = 20;
         let p = &mul_eq();
-        let _ = generate_circuit(p, t);
+        let _ = generate_circuit(p, &[], 0, t, Arch::Harvard);
     }
 
     #[test]
@@ -563,8 +1685,9 @@ mod test {
         let prog = &const_0();
         let args = vec![];
         let time_bound = 1;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -573,8 +1696,9 @@ mod test {
         let prog = &const_0();
         let args = vec![];
         let time_bound = 10;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -582,8 +1706,9 @@ mod test {
         let prog = &b_skip();
         let args = vec![];
         let time_bound = 3; // notice: t < len(encode(prog))
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -591,8 +1716,9 @@ mod test {
         let prog = &programs::overflowing_add();
         let args = vec![];
         let time_bound = 3;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -600,8 +1726,9 @@ mod test {
         let prog = &b_z_skip();
         let args = vec![];
         let time_bound = 4; // notice: t < len(encode(prog))
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -609,8 +1736,9 @@ mod test {
         let prog = &mov0_ret();
         let args = vec![];
         let time_bound = 2;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -618,8 +1746,9 @@ mod test {
         let prog = &simple_str0();
         let args = vec![];
         let time_bound = 3;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -627,8 +1756,9 @@ mod test {
         let prog = &simple_str1();
         let args = vec![];
         let time_bound = 3;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -636,8 +1766,9 @@ mod test {
         let prog = &str3_42();
         let args = vec![];
         let time_bound = 5;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -645,8 +1776,9 @@ mod test {
         let prog = &str2_ldr2_str1_ldr1();
         let args = vec![];
         let time_bound = 7;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -654,8 +1786,9 @@ mod test {
         let prog = &str1_ldr1_str0_ldr0();
         let args = vec![];
         let time_bound = 7;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -663,8 +1796,9 @@ mod test {
         let prog = &str1_str0_ldr1_ldr0();
         let args = vec![];
         let time_bound = 7;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -672,8 +1806,9 @@ mod test {
         let prog = &str1_str2_ldr1_ldr2();
         let args = vec![];
         let time_bound = 7;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -681,8 +1816,9 @@ mod test {
         let prog = &str0();
         let args = vec![];
         let time_bound = 3;
-        let res = convert_and_eval(prog, args, time_bound);
-        assert_eq!(vec![0; res.len()], res)
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -691,8 +1827,9 @@ mod test {
         let prog = &simple_str_n(n);
         let args = vec![];
         let time_bound = n + 2;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -700,8 +1837,9 @@ mod test {
         let prog = &simple_ldr();
         let args = vec![];
         let time_bound = 4;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -709,8 +1847,9 @@ mod test {
         let prog = &mov2pow20_ret0();
         let args = vec![];
         let time_bound = 2;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -718,20 +1857,24 @@ mod test {
         let prog = &mov42_movr3_ret0();
         let args = vec![];
         let time_bound = 4;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
-    #[ignore = "program doesn't return 0"]
     fn mov42() {
+        // The value returned is opened by the circuit
         let prog = &mov42_ret();
-        let args = vec![];
         let time_bound = 2;
-        let res = convert_and_eval(prog, args, time_bound);
-
-        // assert ouput is not all zeros
-        assert_ne!(res.iter().filter(|v| **v != 0).count(), 0);
+        let c = &generate_circuit(prog, &[], 0, time_bound, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let (w, outputs) =
+            encode_witness(prog, vec![], vec![], vec![], time_bound, Arch::Harvard, ctx).unwrap();
+        assert_eq!(outputs, vec![42]);
+        let (res, opened) = eval32_open(c, w);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(opened, outputs);
     }
 
     #[test]
@@ -739,8 +1882,9 @@ mod test {
         let prog = &mov_mov_sub_ret();
         let args = vec![];
         let time_bound = 4;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -748,8 +1892,9 @@ mod test {
         let prog = &ldr_2_args();
         let args = vec![1, 2];
         let time_bound = 5;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -757,8 +1902,9 @@ mod test {
         let prog = &and_000111_111000();
         let args = vec![];
         let time_bound = 5;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -766,8 +1912,9 @@ mod test {
         let prog = &xor_0110_0101();
         let args = vec![];
         let time_bound = 6;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -775,8 +1922,9 @@ mod test {
         let prog = &shr_5_1654560();
         let args = vec![];
         let time_bound = 5;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -784,8 +1932,386 @@ mod test {
         let prog = &rotr_8_0xb301();
         let args = vec![];
         let time_bound = 5;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn mul() {
+        let prog = &mul_0xffffffff_3();
+        let args = vec![];
+        let time_bound = 6;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn umulh() {
+        let prog = &umulh_0xffffffff_3();
+        let args = vec![];
+        let time_bound = 6;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn mul_prove() {
+        // eval32 reduces all values mod 2^32, so products of at least
+        // 2^32 are only checked by the prover and the verifier
+        for (prog, t) in [
+            (&mul_0xffffffff_3(), 6),
+            (&umulh_0xffffffff_3(), 6),
+            (&mul_z(), 5),
+        ] {
+            assert!(prove_and_verify(prog, vec![], t));
+        }
+    }
+
+    #[test]
+    fn mul_sets_z() {
+        let prog = &mul_z();
+        let args = vec![];
+        let time_bound = 5;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn mul_native_2_17_eq_34() {
+        let prog = &mul_native_eq();
+        let args = vec![2, 17, 34];
+        let time_bound = 9;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn b_cond_sub() {
+        use crate::miniram::lang::CondCode::*;
+        let cases = [
+            (Eq, 7, 7),
+            (Ne, 7, 8),
+            (Hs, 8, 7),
+            (Lo, 7, 8),
+            (Mi, 7, 8),
+            (Pl, 8, 7),
+            (Vs, 0x80000000, 1),
+            (Vc, 0x80000000, 0x80000000),
+            (Hi, 0xFFFFFFFF, 1),
+            (Ls, 1, 1),
+            (Ge, 1, 0xFFFFFFFF),
+            (Lt, 0xFFFFFFFF, 1),
+            (Gt, 0x7FFFFFFF, 0x80000000),
+            (Le, 0x80000000, 0x7FFFFFFF),
+        ];
+        for (cc, x, y) in cases {
+            let prog = &sub_b_cond(cc, x, y);
+            let args = vec![];
+            let time_bound = 7;
+            let (res, outputs) = convert_and_eval(prog, args, time_bound);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+    }
+
+    #[test]
+    fn b_cond_add() {
+        use crate::miniram::lang::CondCode::*;
+        let cases = [(Hs, 0xFFFFFFFF, 1), (Vs, 0x7FFFFFFF, 1), (Lo, 1, 1)];
+        for (cc, x, y) in cases {
+            let prog = &add_b_cond(cc, x, y);
+            let args = vec![];
+            let time_bound = 7;
+            let (res, outputs) = convert_and_eval(prog, args, time_bound);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+    }
+
+    #[test]
+    fn cmp_tst() {
+        let prog = &programs::cmp_tst();
+        let args = vec![];
+        let time_bound = 9;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn csel() {
+        let prog = &programs::csel();
+        let args = vec![];
+        let time_bound = 8;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn udiv_urem() {
+        let prog = &programs::udiv_urem();
+        let time_bound = 14;
+        let (res, outputs) = convert_and_eval(prog, vec![], time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+
+        // The prover can't take another remainder of the first UDIV
+        let c = &generate_circuit(prog, &[], 0, time_bound, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let mut w = encode_witness(prog, vec![], vec![], vec![], time_bound, Arch::Harvard, ctx)
+            .unwrap()
+            .0;
+        let size_st = size_local_st(n_reg(prog));
+        w[time_bound * (size_st + 1) + 2] += 5;
+        let res = eval32(c, w);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    #[test]
+    fn data_image() {
+        let prog = &programs::ldr_data();
+        let time_bound = 10;
+        for arch in [Arch::Harvard, Arch::VonNeumann] {
+            let (res, outputs) = convert_and_eval_arch(prog, vec![], vec![], time_bound, arch);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+
+        // The prover can't execute the program on another data image
+        let ctx = &mut ProofCtx::new_deterministic();
+        let w = encode_witness(prog, vec![], vec![], vec![], time_bound, Arch::Harvard, ctx);
+        let mut other = programs::ldr_data();
+        other.data[1].1 += 1;
+        let c = &generate_circuit(&other, &[], 0, time_bound, Arch::Harvard);
+        let res = eval32(c, w.unwrap().0);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    #[test]
+    fn memory_regions() {
+        let prog = &programs::str_regions(8);
+        let time_bound = 13;
+        for arch in [Arch::Harvard, Arch::VonNeumann] {
+            let (res, outputs) = convert_and_eval_arch(prog, vec![], vec![], time_bound, arch);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+
+        // The prover can't store to a read-only region or outside the
+        // regions, i.e execute the program as if it had no regions
+        let arch = Arch::Harvard;
+        for adr in [101, 50] {
+            let prog = &programs::str_regions(adr);
+            let mut other = programs::str_regions(adr);
+            other.regions.clear();
+            let ctx = &mut ProofCtx::new_deterministic();
+            let w = encode_witness(&other, vec![], vec![], vec![], time_bound, arch, ctx);
+            let c = &generate_circuit(prog, &[], 0, time_bound, arch);
+            let res = eval32(c, w.unwrap().0);
+            assert_ne!(vec![0; res.len()], res);
+        }
+    }
+
+    #[test]
+    fn out() {
+        let prog = &programs::out_fib();
+        let time_bound = 34;
+        let n_out = 5;
+        let c = &generate_circuit(prog, &[], n_out, time_bound, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let (mut w, outputs) =
+            encode_witness(prog, vec![], vec![], vec![], time_bound, Arch::Harvard, ctx).unwrap();
+        assert_eq!(outputs, vec![5, 2, 3, 5, 8, 13]);
+        let (res, opened) = eval32_open(c, w.clone());
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(opened, outputs);
+
+        // The prover can't change the values output
+        let size_st = size_local_st(n_reg(prog));
+        w[time_bound * (size_st + 2) + 1] += 1;
+        let res = eval32(c, w);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    #[test]
+    fn orr_mvn_shl() {
+        let prog = &programs::orr_mvn_shl();
+        let args = vec![];
+        let time_bound = 14;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn shift_r() {
+        let prog = &programs::shift_r();
+        let args = vec![];
+        let time_bound = 14;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn alu_c() {
+        let prog = &programs::alu_c();
+        let args = vec![];
+        let time_bound = 12;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn b_imm() {
+        let prog = &programs::b_imm();
+        let args = vec![];
+        let time_bound = 14;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+
+        // The target of the backward BREL is pc plus a negative offset
+        // mod 2^32, which eval32 doesn't check
+        assert!(prove_and_verify(prog, vec![], time_bound));
+    }
+
+    #[test]
+    fn bl() {
+        let prog = &programs::bl();
+        let args = vec![];
+        let time_bound = 12;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn bl_lr() {
+        let prog = &programs::bl_lr();
+        let args = vec![];
+        let time_bound = 5;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn ldrb_strb() {
+        let prog = &programs::ldrb_strb();
+        let args = vec![0x44332211];
+        let time_bound = 21;
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn isqrt_hint() {
+        let prog = &programs::isqrt_hint();
+        let args = vec![17];
+        let private = vec![4];
+        let time_bound = 11;
+        let (res, outputs) = convert_and_eval_tapes(prog, args, vec![], private, time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    #[test]
+    fn read_public() {
+        let prog = &programs::read_public();
+        let time_bound = 8;
+        let (res, outputs) = convert_and_eval_tapes(prog, vec![], vec![3, 4], vec![7], time_bound);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+
+        // The prover can't change the public tape
+        let c = &generate_circuit(prog, &[3, 5], 0, time_bound, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let w = encode_witness(
+            prog,
+            vec![],
+            vec![3, 4],
+            vec![7],
+            time_bound,
+            Arch::Harvard,
+            ctx,
+        );
+        let res = eval32(c, w.unwrap().0);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    #[test]
+    fn von_neumann() {
+        let progs = [
+            (programs::mul_eq(), vec![2, 17, 34], 22),
+            (programs::bl(), vec![], 12),
+            (programs::ldrb_strb(), vec![0x44332211], 21),
+        ];
+        for (prog, args, time_bound) in progs {
+            let (res, outputs) =
+                convert_and_eval_arch(&prog, args, vec![], time_bound, Arch::VonNeumann);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+    }
+
+    #[test]
+    fn von_neumann_fetch() {
+        // The prover can't change the instructions fetched from memory
+        let prog = &mul_eq();
+        let args = vec![2, 17, 34];
+        let time_bound = 22;
+        let c = &generate_circuit(prog, &[], 0, time_bound, Arch::VonNeumann);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let mut w = encode_witness(
+            prog,
+            args,
+            vec![],
+            vec![],
+            time_bound,
+            Arch::VonNeumann,
+            ctx,
+        )
+        .unwrap()
+        .0;
+        // Change the high word of the second instruction fetched
+        let size_st = size_local_st(n_reg(prog));
+        w[time_bound * (size_st + 2) + 2] ^= 1;
+        let res = eval32(c, w);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    #[test]
+    fn von_neumann_str_code() {
+        // The program can't change its code in memory, even where the
+        // code isn't fetched again
+        let prog = &programs::str_code();
+        let res = interpret_von_neumann(prog, vec![], vec![], vec![], None);
+        assert_eq!(res.unwrap_err(), "storing to the program code");
+
+        // Witness of the execution with the store, where the word
+        // stored to had the value of the code
+        let (_, outputs, mut lsts) = interpret(prog, vec![], vec![], vec![], None).unwrap();
+        let code = code_words(prog);
+        for s in &mut lsts {
+            if let MemAccess::Write { addr, old, .. } = &mut s.ma {
+                *old = code[usize::try_from(*addr - ADR_CODE).unwrap()];
+            }
+        }
+        let time_bound = lsts.len();
+        let ctx = &mut ProofCtx::new_deterministic();
+        let w = convert_localstates(lsts, n_reg(prog), &outputs, &[], Some(code), false, ctx);
+        let c = &generate_circuit(prog, &[], 0, time_bound, Arch::VonNeumann);
+        let res = eval32(c, w);
+        assert_ne!(vec![0; res.len()], res);
     }
 
     #[test]
@@ -793,8 +2319,9 @@ mod test {
         let prog = &mul_eq();
         let args = vec![1, 1, 1];
         let time_bound = 15;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -803,8 +2330,9 @@ mod test {
         dbg!(&prog);
         let args = vec![1, 2, 2];
         let time_bound = 15;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -812,8 +2340,9 @@ mod test {
         let prog = &mul_eq();
         let args = vec![2, 2, 4];
         let time_bound = 22;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
     }
 
     #[test]
@@ -821,16 +2350,83 @@ mod test {
         let prog = &mul_eq();
         let args = vec![2, 17, 34];
         let time_bound = 22;
-        let res = convert_and_eval(prog, args, time_bound);
+        let (res, outputs) = convert_and_eval(prog, args, time_bound);
         assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+    }
+
+    fn convert_and_eval(p: &Prog, args: Vec<Word>, t: usize) -> (Vec<u32>, Vec<Word>) {
+        convert_and_eval_tapes(p, args, vec![], vec![], t)
+    }
+
+    #[test]
+    fn register_count() {
+        // Each step only carries the registers used by the program
+        assert_eq!(n_reg(&mov42_ret()), 4);
+        assert_eq!(n_reg(&programs::bl()), 16);
+        let prog = &sub42_r31_ret();
+        assert_eq!(n_reg(prog), 32);
+        let time_bound = 3;
+        for arch in [Arch::Harvard, Arch::VonNeumann] {
+            let (res, outputs) = convert_and_eval_arch(prog, vec![], vec![], time_bound, arch);
+            assert_eq!(vec![0; res.len()], res);
+            assert_eq!(outputs[0], 0);
+        }
+    }
+
+    #[test]
+    fn private_program() {
+        let prog = &programs::bl();
+        let time_bound = 12;
+        let (res, outputs) = convert_and_eval_arch(prog, vec![], vec![], time_bound, Arch::Private);
+        assert_eq!(vec![0; res.len()], res);
+        assert_eq!(outputs[0], 0);
+
+        // The prover can't execute a program with another digest
+        let ctx = &mut ProofCtx::new_deterministic();
+        let w = encode_witness(prog, vec![], vec![], vec![], time_bound, Arch::Private, ctx);
+        let mut digest = program_digest(prog);
+        digest[0] ^= 1;
+        let (n_instr, n_reg) = (prog.code.len(), n_reg(prog));
+        let c = &generate_circuit_private(&digest, n_instr, n_reg, &[], &[], (&[], 0), time_bound);
+        let res = eval32(c, w.unwrap().0);
+        assert_ne!(vec![0; res.len()], res);
+    }
+
+    /// Returns whether the verifier accepts the proof of the prover
+    /// of the execution of p on args, with the outputs of encode_witness
+    fn prove_and_verify(p: &Prog, args: Vec<Word>, t: usize) -> bool {
+        let c = generate_circuit(p, &[], 0, t, Arch::Harvard);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let (w, outputs) = encode_witness(p, args, vec![], vec![], t, Arch::Harvard, ctx).unwrap();
+        crate::runners::prove_verify(c, w, outputs)
+    }
+
+    fn convert_and_eval_arch(
+        p: &Prog,
+        args: Vec<Word>,
+        private: Vec<Word>,
+        t: usize,
+        arch: Arch,
+    ) -> (Vec<u32>, Vec<Word>) {
+        let c = &generate_circuit(p, &[], 0, t, arch);
+        let ctx = &mut ProofCtx::new_deterministic();
+        let (w, outputs) = encode_witness(p, args, vec![], private, t, arch, ctx).unwrap();
+        (eval32(c, w), outputs)
     }
 
-    fn convert_and_eval(p: &Prog, args: Vec<Word>, t: usize) -> Vec<u32> {
-        let c = &generate_circuit(p, t);
+    fn convert_and_eval_tapes(
+        p: &Prog,
+        args: Vec<Word>,
+        public: Vec<Word>,
+        private: Vec<Word>,
+        t: usize,
+    ) -> (Vec<u32>, Vec<Word>) {
+        let c = &generate_circuit(p, &public, 0, t, Arch::Harvard);
         //pp::print(c, None);
         let ctx = &mut ProofCtx::new_deterministic();
-        let w = encode_witness(p, args, t, ctx).unwrap();
+        let (w, outputs) = encode_witness(p, args, public, private, t, Arch::Harvard, ctx).unwrap();
         // dbg!(&w);
-        eval32(c, w)
+        (eval32(c, w), outputs)
     }
 }
@@ -1,9 +1,9 @@
 use zerocopy::IntoBytes;
 
 use crate::miniram::lang::Word;
-use crate::ProofCtx;
 use backend::quicksilver::prove::prove32;
 use backend::quicksilver::verify::verify32;
+use backend::ProofCtx;
 use std::io::Write;
 use std::net::TcpListener;
 use std::net::TcpStream;
@@ -37,6 +37,7 @@ pub fn run_v(
     port: u16,
     port_vole: u16,
     c: Circuit<Word>,
+    expected: Option<Vec<Word>>,
     mut ctx: ProofCtx,
 ) -> std::io::Result<()> {
     print!("Verifier: Connecting to VOLE dealer on port {port_vole}... ");
@@ -49,8 +50,15 @@ pub fn run_v(
     println!("Running verifier");
     let chan = VerifierTcpChannel::new(stream_other, stream_vole);
     ctx.start_time("verifier");
-    verify32(c, chan, ctx);
-    Ok(())
+    let outputs = verify32(c, chan, ctx);
+    println!("Public outputs: {outputs:?}");
+    match expected {
+        Some(expected) if outputs != expected => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("expected the public outputs {expected:?}"),
+        )),
+        _ => Ok(()),
+    }
 }
 
 pub fn run_vole(port: u16, mut ctx: ProofCtx) -> std::io::Result<()> {
@@ -121,5 +129,28 @@ fn rcv_extend(stream: &mut TcpStream) -> std::io::Result<u64> {
     Ok(channel::recv_u64(stream))
 }
 
+/// Runs the VOLE dealer, the prover and the verifier of c on localhost
+/// with the witness w, and returns whether the verifier accepts with
+/// the public outputs expected
+#[cfg(test)]
+pub fn prove_verify(c: Circuit<Word>, w: Vec<Word>, expected: Vec<Word>) -> bool {
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::thread::{sleep, spawn};
+    use std::time::Duration;
+    // Each run takes two ports, as tests run in parallel
+    static PORT: AtomicU16 = AtomicU16::new(47100);
+    let port_vole = PORT.fetch_add(2, Ordering::Relaxed);
+    let port = port_vole + 1;
+    // The dealer accepts the prover before the verifier, which must
+    // connect after the prover listens
+    spawn(move || run_vole(port_vole, ProofCtx::new_deterministic()));
+    sleep(Duration::from_millis(200));
+    let c_ = c.clone();
+    spawn(move || run_p(port, port_vole, c_, w, ProofCtx::new_deterministic()));
+    sleep(Duration::from_millis(500));
+    let v = spawn(move || run_v(port, port_vole, c, Some(expected), ProofCtx::new_random()));
+    matches!(v.join(), Ok(Ok(())))
+}
+
 #[cfg(test)]
 mod tests {}
@@ -309,7 +309,13 @@ pub fn eval64(c: &Circuit<u64>, mut wires: Vec<u64>) -> Vec<u64> {
 }
 
 /// Evaluate a circuit of u32 values
-pub fn eval32(c: &Circuit<u32>, mut wires: Vec<u32>) -> Vec<u32> {
+pub fn eval32(c: &Circuit<u32>, wires: Vec<u32>) -> Vec<u32> {
+    eval32_open(c, wires).0
+}
+
+/// Evaluate a circuit of u32 values, returning the outputs and the
+/// values of the opened wires
+pub fn eval32_open(c: &Circuit<u32>, mut wires: Vec<u32>) -> (Vec<u32>, Vec<u32>) {
     // dbg!(c);
     let gates = &c.gates;
     let consts = &c.consts;
@@ -568,7 +574,8 @@ pub fn eval32(c: &Circuit<u32>, mut wires: Vec<u32>) -> Vec<u32> {
         // dbg!(&wires);
     }
     pp::print(c, Some(&wires));
-    out
+    let opened = c.openings.iter().map(|id| wires[id - ARG0]).collect();
+    (out, opened)
 }
 
 /// Counts number of gates
@@ -639,6 +646,7 @@ mod tests {
             n_decode32: 0,
             n_check_all_eq: 0,
             n_check_all_eq_pairs: 0,
+            openings: vec![],
         };
         let res = eval64(c, wires);
         assert_eq!(*res.last().unwrap(), 42)
@@ -668,6 +676,7 @@ mod tests {
             n_decode32: 0,
             n_check_all_eq: 0,
             n_check_all_eq_pairs: 0,
+            openings: vec![],
         };
         let res = eval64(c, wires);
         assert_eq!(*res.last().unwrap(), 42 + 34)
@@ -38,11 +38,12 @@ pub struct Builder<T> {
     n_decode32: usize,
     n_check_all_eq: usize,
     n_check_all_eq_pairs: usize,
+    openings: Vec<usize>,
     offset_arg0: bool,
     enable_z2_ops: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Res<T> {
     pub gates: Vec<usize>,
     pub consts: Vec<T>,
@@ -68,6 +69,8 @@ pub struct Res<T> {
     pub n_check_all_eq: usize,
     /// Number of check_all_eq_but_one pairs
     pub n_check_all_eq_pairs: usize,
+    /// Ids of the wires whose values are revealed to the verifier
+    pub openings: Vec<usize>,
 }
 
 impl<T> Builder<T> {
@@ -88,6 +91,7 @@ impl<T> Builder<T> {
             n_decode32: 0,
             n_check_all_eq: 0,
             n_check_all_eq_pairs: 0,
+            openings: Vec::new(),
             offset_arg0: false,
             enable_z2_ops: false,
         }
@@ -449,6 +453,12 @@ impl<T> Builder<T> {
         self.n_gates += 1;
     }
 
+    /// Reveal the value of wire x to the verifier, i.e make it a
+    /// public output of the circuit
+    pub fn open(&mut self, x: usize) {
+        self.openings.push(x);
+    }
+
     /// Reduce builder to its result
     pub fn build(mut self, outputs: &[usize]) -> Res<T> {
         #[cfg(test)]
@@ -473,6 +483,7 @@ impl<T> Builder<T> {
             n_decode32: self.n_decode32,
             n_check_all_eq: self.n_check_all_eq,
             n_check_all_eq_pairs: self.n_check_all_eq_pairs,
+            openings: self.openings,
         }
     }
 
@@ -1,4 +1,5 @@
 use super::builder::Builder;
+use crate::sha256;
 
 pub fn bit_comparator<T>(
     b: &mut Builder<T>,
@@ -40,6 +41,70 @@ pub fn bitwise_xor_u32_bits(b: &mut Builder<u32>, xbits: usize, ybits: usize) ->
     b.encode32_range(core::array::from_fn(|i| zs[i]))
 }
 
+/// Computes the upper 32 bits of the 64 bit product of x and y,
+/// given the bit-decompositions of x and y (see mul_u32_bits).
+pub fn umulh_u32_bits(b: &mut Builder<u32>, xbits: usize, ybits: usize, zero: usize) -> usize {
+    mul_u32_bits(b, xbits, ybits, zero).1
+}
+
+/// Computes the lower and upper 32 bits of the 64 bit product of x
+/// and y, given the bit-decompositions of x and y.
+///
+/// The words are split into 16 bit limbs x = x1*2^16 + x0 and
+/// y = y1*2^16 + y0, such that each partial product fits in a word
+/// without wrapping. With the middle limbs
+///
+///   mid = (x0*y0 >> 16) + (x0*y1 mod 2^16) + (x1*y0 mod 2^16)
+///
+/// the lower word is (x0*y0 mod 2^16) + (mid mod 2^16)*2^16, and the
+/// upper word is
+///
+///   x1*y1 + (x0*y1 >> 16) + (x1*y0 >> 16) + carry
+///
+/// where carry is mid >> 16.
+pub fn mul_u32_bits(
+    b: &mut Builder<u32>,
+    xbits: usize,
+    ybits: usize,
+    zero: usize,
+) -> (usize, usize) {
+    let x0 = encode_u16_bits(b, xbits, zero);
+    let x1 = encode_u16_bits(b, xbits + 16, zero);
+    let y0 = encode_u16_bits(b, ybits, zero);
+    let y1 = encode_u16_bits(b, ybits + 16, zero);
+
+    let x0y0 = b.mul(x0, y0);
+    let x0y1 = b.mul(x0, y1);
+    let x1y0 = b.mul(x1, y0);
+    let x1y1 = b.mul(x1, y1);
+
+    let x0y0 = b.decode32(x0y0);
+    let x0y1 = b.decode32(x0y1);
+    let x1y0 = b.decode32(x1y0);
+
+    let x0y0_hi = encode_u16_bits(b, x0y0 + 16, zero);
+    let x0y1_lo = encode_u16_bits(b, x0y1, zero);
+    let x0y1_hi = encode_u16_bits(b, x0y1 + 16, zero);
+    let x1y0_lo = encode_u16_bits(b, x1y0, zero);
+    let x1y0_hi = encode_u16_bits(b, x1y0 + 16, zero);
+
+    let mid = b.add(&[x0y0_hi, x0y1_lo, x1y0_lo]);
+    let mid = b.decode32(mid);
+    let carry = encode_u16_bits(b, mid + 16, zero);
+
+    let lo = b.encode32_range(core::array::from_fn(|i| match i < 16 {
+        true => x0y0 + i,
+        false => mid + i - 16,
+    }));
+    let hi = b.add(&[x1y1, x0y1_hi, x1y0_hi, carry]);
+    (lo, hi)
+}
+
+/// Encodes the 16 bits x0, .., x15 as a word
+fn encode_u16_bits<T>(b: &mut Builder<T>, x0: usize, zero: usize) -> usize {
+    b.encode32_range(core::array::from_fn(|i| if i < 16 { x0 + i } else { zero }))
+}
+
 /// Inputs: x0, .., xn
 ///         y0, .., yn
 ///
@@ -196,39 +261,207 @@ fn switch<T>(b: &mut Builder<T>, x: usize, y: usize, z: usize, one: usize) -> (u
 /// Decodes lower 32 bit instr as encoded by frontend::miniram::encode::encode_instr_u64
 ///
 /// Input: i, the (index of the) constant holding the lower 32 bit
-/// of the instruction.
-pub fn decode_lo_instr32<T>(b: &mut Builder<T>, i: usize) -> (usize, usize) {
+/// of the instruction, and reg_bits, the number of bits of the
+/// register referenced by arg1.
+pub fn decode_lo_instr32<T>(b: &mut Builder<T>, i: usize, reg_bits: usize) -> (usize, usize) {
     let i0 = b.decode32(i);
-    let arg1 = b.encode4(i0);
+    let arg1 = encode_bits(b, i0, reg_bits);
     let arg1_word = i;
     (arg1, arg1_word)
 }
 
+/// Encodes the n bits x0, ..., x0 + n - 1 (least significant bit
+/// first) as a word
+pub fn encode_bits<T>(b: &mut Builder<T>, x0: usize, n: usize) -> usize {
+    match n {
+        4 => b.encode4(x0),
+        5 => b.encode5(x0),
+        8 => b.encode8(x0),
+        _ => {
+            // Horner's method, from the most significant bit
+            let mut acc = x0 + n - 1;
+            for x in (x0..x0 + n - 1).rev() {
+                acc = b.add(&[acc, acc, x]);
+            }
+            acc
+        }
+    }
+}
+
+/// Ids of the fields of a decoded high 32 bit instruction, see
+/// decode_hi_instr32.
+pub struct HiInstr {
+    /// Opcode
+    pub op: usize,
+    /// Least significant bit of the bit-decomposition of the opcode
+    pub op_bits: usize,
+    /// Destination register
+    pub dst: usize,
+    /// Register of first argument
+    pub arg0: usize,
+    /// 1 only for LDR/ STR (and byte/ halfword variants)
+    pub is_mem: usize,
+    /// 1 only for LDR (and byte/ halfword variants)
+    pub is_load: usize,
+    /// Field #4 (shift amount or condition code)
+    pub field4: usize,
+    /// Least significant bit of the bit-decomposition of field #4
+    pub field4_bits: usize,
+}
+
 /// Decodes high 32 bit instr as encoded by
 /// frontend::miniram::encode::encode_instr_u64
 ///
 /// Input: i, the (index of the) constant holding the high 32 bit
-/// of the instruction.
-pub fn decode_hi_instr32<T>(
-    b: &mut Builder<T>,
-    i: usize,
-) -> (usize, usize, usize, usize, usize, usize, usize) {
+/// of the instruction, and reg_bits, the number of (least
+/// significant) bits of the register fields that are decoded.
+pub fn decode_hi_instr32<T>(b: &mut Builder<T>, i: usize, reg_bits: usize) -> HiInstr {
     // b.dbg()
     // Destruct instruction into its bit-decomposition
     let i0 = b.decode32(i);
 
     let op = b.encode8(i0 + 24);
-    // lsb of op is 1 only for LDR
+    // lsb of op is 1 only for LDR/ LDRB/ LDRH
     let is_load = i0 + 24;
-    // next most lsb of op is 1 only for LDR/ STR
+    // next most lsb of op is 1 only for LDR/ STR and variants
     let is_mem = i0 + 25;
-    // op >> 5 is 1 only if op is RET
-    let is_ret = i0 + (24 + 5);
 
-    let dst = b.encode4(i0 + 16);
-    let arg0 = b.encode4(i0 + 8);
+    let dst = encode_bits(b, i0 + 16, reg_bits);
+    let arg0 = encode_bits(b, i0 + 8, reg_bits);
     let field4 = b.encode5(i0);
-    (op, dst, arg0, is_mem, is_load, is_ret, field4)
+    HiInstr {
+        op,
+        op_bits: i0 + 24,
+        dst,
+        arg0,
+        is_mem,
+        is_load,
+        field4,
+        field4_bits: i0,
+    }
+}
+
+/// Computes the SHA-256 digest (as described in FIPS 180-4) of the
+/// words xs, i.e of the big-endian bytes of xs.
+///
+/// The padding only depends on the number of words, and is added as
+/// constants.
+pub fn sha256(b: &mut Builder<u32>, xs: &[usize], zero: usize) -> [usize; 8] {
+    let one = b.push_const(1);
+    let one = b.const_(one);
+    let bits = |c: u32| core::array::from_fn(|i| if (c >> i) & 1 == 1 { one } else { zero });
+    let mut words = xs
+        .iter()
+        .map(|x| {
+            let x = b.decode32(*x);
+            core::array::from_fn(|i| x + i)
+        })
+        .collect::<Vec<_>>();
+    for w in &sha256::pad_words(&vec![0; xs.len()])[xs.len()..] {
+        words.push(bits(*w));
+    }
+    let k = sha256::K32.map(bits);
+    let mut h = sha256::H32.map(bits);
+    for block in words.chunks(16) {
+        h = sha256_compress(b, h, block, &k, zero);
+    }
+    h.map(|h| b.encode32_range(h))
+}
+
+/// Bits of a word, least significant first
+type Bits = [usize; 32];
+
+/// Applies the SHA-256 compression function to the hash values h
+/// and the 16 word block, where k holds the bits of the constants
+/// sha256::K32.
+///
+/// All words are kept as bits, and additions are done bitwise (see
+/// add_u32_bits), as the words decoded by the circuit must be below
+/// 2^32 when proving, unlike sums of words in the ring.
+fn sha256_compress(
+    b: &mut Builder<u32>,
+    h: [Bits; 8],
+    block: &[Bits],
+    k: &[Bits; 64],
+    zero: usize,
+) -> [Bits; 8] {
+    // message schedule
+    let mut w = block.to_vec();
+    for t in 16..64 {
+        let s0 = sha256_sigma(b, w[t - 15], [7, 18], 3, zero);
+        let s1 = sha256_sigma(b, w[t - 2], [17, 19], 10, zero);
+        let x = add_u32_bits(b, &[s1, w[t - 7], s0, w[t - 16]], zero);
+        w.push(x);
+    }
+
+    // working variables
+    let mut v = h;
+    for t in 0..64 {
+        let [a, b_, c, d, e, f, g, h_] = v;
+        let s1 = xor3_u32(b, [rotr(e, 6), rotr(e, 11), rotr(e, 25)]);
+        // ch = (e & f) ^ (!e & g) = g + e*(f - g) for each bit
+        let ch = core::array::from_fn(|i| {
+            let tmp = b.sub(f[i], g[i]);
+            let tmp = b.mul(e[i], tmp);
+            b.add(&[g[i], tmp])
+        });
+        let t1 = add_u32_bits(b, &[h_, s1, ch, k[t], w[t]], zero);
+        let s0 = xor3_u32(b, [rotr(a, 2), rotr(a, 13), rotr(a, 22)]);
+        // maj = (a & b) ^ (a & c) ^ (b & c) = a*b + c*(a ^ b) for
+        // each bit
+        let maj = core::array::from_fn(|i| {
+            let ab = b.mul(a[i], b_[i]);
+            let tmp = b.xor_bits(&[a[i], b_[i]]);
+            let tmp = b.mul(c[i], tmp);
+            b.add(&[ab, tmp])
+        });
+        let t2 = add_u32_bits(b, &[s0, maj], zero);
+
+        let e_new = add_u32_bits(b, &[d, t1], zero);
+        let a_new = add_u32_bits(b, &[t1, t2], zero);
+        v = [a_new, a, b_, c, e_new, e, f, g];
+    }
+    core::array::from_fn(|i| add_u32_bits(b, &[h[i], v[i]], zero))
+}
+
+/// Computes ROTR_r0(x) ^ ROTR_r1(x) ^ SHR_s(x) of the word x, given
+/// as bits.
+fn sha256_sigma(b: &mut Builder<u32>, x: Bits, r: [usize; 2], s: usize, zero: usize) -> Bits {
+    let shr = core::array::from_fn(|i| if i + s < 32 { x[i + s] } else { zero });
+    xor3_u32(b, [rotr(x, r[0]), rotr(x, r[1]), shr])
+}
+
+/// Bits of the word x rotated right by n, given the bits of x.
+fn rotr(x: Bits, n: usize) -> Bits {
+    core::array::from_fn(|i| x[(i + n) % 32])
+}
+
+/// Computes the bitwise xor of three words given as bits.
+fn xor3_u32(b: &mut Builder<u32>, xs: [Bits; 3]) -> Bits {
+    core::array::from_fn(|i| b.xor_bits(&[xs[0][i], xs[1][i], xs[2][i]]))
+}
+
+/// Computes the sum modulo 2^32 of the words xs given as bits, with
+/// a ripple-carry adder for each addition. Each bit costs two
+/// multiplications, as the sum of x, y and the carry c is
+/// (x ^ y) ^ c, and the next carry is x*y + c*(x ^ y).
+fn add_u32_bits(b: &mut Builder<u32>, xs: &[Bits], zero: usize) -> Bits {
+    let mut acc = xs[0];
+    for y in &xs[1..] {
+        let mut c = zero;
+        acc = core::array::from_fn(|i| {
+            let xy = b.mul(acc[i], y[i]);
+            let xy2 = b.add(&[xy, xy]);
+            let tmp = b.add(&[acc[i], y[i]]);
+            let x_y = b.sub(tmp, xy2);
+            let cx_y = b.mul(c, x_y);
+            let cx_y2 = b.add(&[cx_y, cx_y]);
+            let tmp = b.add(&[x_y, c]);
+            c = b.add(&[xy, cx_y]);
+            b.sub(tmp, cx_y2)
+        });
+    }
+    acc
 }
 
 #[cfg(test)]
@@ -648,4 +881,29 @@ mod tests {
         let res = eval32(c, wires);
         assert_eq!(res, [3]);
     }
+
+    #[test]
+    fn test_mul() {
+        let n_in = 2;
+        let mut b = Builder::new(n_in);
+        let zero = b.push_const(0);
+        let zero = b.const_(zero);
+        let xbits = b.decode32(ARG0);
+        let ybits = b.decode32(ARG0 + 1);
+        let (lo, hi) = mul_u32_bits(&mut b, xbits, ybits, zero);
+        let c = &b.build(&[lo, hi]);
+
+        for (x, y) in [
+            (0, 0),
+            (1, 1),
+            (3, 0xFFFFFFFF),
+            (0xFFFF, 0x10001),
+            (0xFFFFFFFF, 0xFFFFFFFF),
+            (0x12345678, 0x9abcdef0),
+        ] {
+            let xy = u64::from(x) * u64::from(y);
+            let res = eval32(c, vec![x, y]);
+            assert_eq!(res, [xy as u32, (xy >> 32) as u32]);
+        }
+    }
 }
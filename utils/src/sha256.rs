@@ -42,6 +42,69 @@ pub fn pad(msg: &str) -> Vec<u32> {
     res
 }
 
+/// Pads a message of whole words according to FIPS 180-4, where the
+/// message is the big-endian bytes of the words.
+pub fn pad_words(words: &[u32]) -> Vec<u32> {
+    // 1 word to prepend 1 to msg, and 2 words to encode msg length
+    let n_blocks = (words.len() + 3).div_ceil(16);
+    let mut res = words.to_vec();
+    res.push(0x80000000);
+    res.resize(n_blocks * 16 - 2, 0);
+    let l = u64::try_from(words.len()).unwrap() * 32;
+    res.push((l >> 32) as u32);
+    res.push(l as u32);
+    res
+}
+
+/// Computes the SHA-256 digest of a message of whole words, where
+/// the message is the big-endian bytes of the words.
+pub fn digest(words: &[u32]) -> [u32; 8] {
+    let mut h = H32;
+    for block in pad_words(words).chunks(16) {
+        compress(&mut h, block)
+    }
+    h
+}
+
+/// Applies the SHA-256 compression function to the hash values h
+/// and a 16 word block.
+pub fn compress(h: &mut [u32; 8], block: &[u32]) {
+    let mut w = block.to_vec();
+    for t in 16..64 {
+        let s0 = w[t - 15].rotate_right(7) ^ w[t - 15].rotate_right(18) ^ (w[t - 15] >> 3);
+        let s1 = w[t - 2].rotate_right(17) ^ w[t - 2].rotate_right(19) ^ (w[t - 2] >> 10);
+        let x = s1
+            .wrapping_add(w[t - 7])
+            .wrapping_add(s0)
+            .wrapping_add(w[t - 16]);
+        w.push(x);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = *h;
+    for t in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = hh
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K32[t])
+            .wrapping_add(w[t]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        hh = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (x, y) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+        *x = x.wrapping_add(y)
+    }
+}
+
 pub fn parse_mac(mac: &str) -> [u32; 16] {
     let mut bytes = [0; 64];
     for (i, x) in hex::decode(mac).unwrap().iter().enumerate() {
@@ -55,6 +118,11 @@ pub fn parse_mac(mac: &str) -> [u32; 16] {
     res
 }
 
+/// Initial hash values of SHA-256.
+pub const H32: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
 /// Constants necessary for SHA-256 family of digests.
 pub const K32: [u32; 64] = [
     0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
@@ -66,3 +134,22 @@ pub const K32: [u32; 64] = [
     0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
     0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest() {
+        let res = digest(&[]);
+        let expected =
+            parse_mac("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(res, expected[..8]);
+
+        // "abcd"
+        let res = digest(&[0x61626364]);
+        let expected =
+            parse_mac("88d4266fd4e6338d13b845fcf289579d209c897823b9217da3e161936f031589");
+        assert_eq!(res, expected[..8]);
+    }
+}